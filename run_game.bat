@@ -1 +1 @@
-battlesnake play --url http://localhost:8000 --url http://localhost:8000 --url http://localhost:8001 --url http://localhost:8001 --browser
\ No newline at end of file
+battlesnake play --url http://localhost:8000 --url http://localhost:8000 --url http://localhost:8000/bots/greedy --url http://localhost:8000/bots/greedy --browser
@@ -0,0 +1,29 @@
+// How the snake looks on the board and what it reports about itself on GET /.
+//
+// Set per instance in the [appearance] section of the config, or with SNAKE_AUTHOR,
+// SNAKE_COLOR, SNAKE_HEAD, SNAKE_TAIL and SNAKE_VERSION, so team mates on different
+// servers can be told apart in replays.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Appearance {
+    pub author: String,
+    pub color: String,
+    pub head: String,
+    pub tail: String,
+    pub version: String,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Appearance {
+            author: "Group 18".to_string(),
+            color: "#e83d84".to_string(),
+            head: "tiger-king".to_string(),
+            tail: "coffee".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
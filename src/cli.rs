@@ -0,0 +1,240 @@
+// Command line tools that run instead of the server when the binary is started with a
+// subcommand, for example `cargo run --release -- book --turns 2`
+
+use log::{error, info};
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::config::config;
+use crate::logic::opening::{self, OpeningBook};
+use crate::logic::mm_search::search_depth;
+use crate::logic::ordering::ORDERINGS;
+use crate::logic::perft;
+use crate::logic::simple::{JointMove, SimpleBoard};
+use crate::notation::Position;
+use crate::render::{self, Scene};
+use crate::{Board, GameInfo, GameState};
+use serde::Deserialize;
+
+// Exit code of the subcommand, or None when the arguments don't name one
+pub fn run(args: &[String]) -> Option<i32> {
+    let code = match args.first()?.as_str() {
+        "book" => book(&args[1..]),
+        "render" => render(&args[1..]),
+        "perft" => perft(&args[1..]),
+        "bench" => bench(&args[1..]),
+        _ => return None,
+    };
+    Some(code)
+}
+
+// Value following a `--name` flag
+fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+fn parsed_flag<T: std::str::FromStr>(args: &[String], name: &str, default: T) -> Result<T, String> {
+    match flag(args, name) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("invalid value {} for {}", value, name)),
+        None => Ok(default),
+    }
+}
+
+// Requests from a file with one /move request body per line
+fn read_states(path: &str) -> Result<Vec<GameState>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| format!("{}: {}", path, e)))
+        .collect()
+}
+
+// Positions from a file of /move request bodies, searched from the point of view of
+// the `you` snake and its squad
+fn read_positions(path: &str) -> Result<Vec<(SimpleBoard, i32)>, String> {
+    Ok(read_states(path)?
+        .into_iter()
+        .map(|state| {
+            let game_info = GameInfo::new(state.game.id.clone(), state.game.timeout, vec![state.you.id.clone()]);
+            (SimpleBoard::from(&state.board, &game_info), state.turn)
+        })
+        .collect())
+}
+
+// book [--out book.json] [--positions moves.jsonl] [--turns 1] [--time 1000]
+fn book(args: &[String]) -> i32 {
+    match generate_book(args) {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("{}", e);
+            1
+        }
+    }
+}
+
+fn generate_book(args: &[String]) -> Result<(), String> {
+    let out = flag(args, "--out").unwrap_or(config().book.path.as_str());
+    let turns = parsed_flag(args, "--turns", 1)?;
+    let time = parsed_flag(args, "--time", 1000)?;
+    let positions = match flag(args, "--positions") {
+        Some(path) => read_positions(path)?,
+        None => opening::standard_starts().into_iter().map(|b| (b, 0)).collect(),
+    };
+
+    info!("Generating a {} turn opening book from {} positions", turns, positions.len());
+    let book: OpeningBook = opening::generate(positions, turns, time);
+    book.save(Path::new(out)).map_err(|e| format!("{}: {}", out, e))?;
+    info!("Wrote {} positions to {}", book.len(), out);
+    Ok(())
+}
+
+// render (--position pos.txt | --positions moves.jsonl) [--svg replay.svg] [--plain]
+fn render(args: &[String]) -> i32 {
+    match render_positions(args) {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("{}", e);
+            1
+        }
+    }
+}
+
+fn render_positions(args: &[String]) -> Result<(), String> {
+    let (boards, titles): (Vec<Board>, Vec<String>) = match (flag(args, "--position"), flag(args, "--positions")) {
+        (Some(path), _) => {
+            let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            let position: Position = text.parse().map_err(|e| format!("{}: {}", path, e))?;
+            (vec![position.board()], vec![path.to_string()])
+        }
+        (None, Some(path)) => read_states(path)?
+            .into_iter()
+            .map(|state| (state.board, format!("turn {}", state.turn)))
+            .unzip(),
+        (None, None) => return Err("render needs --position or --positions".to_string()),
+    };
+    let scenes: Vec<Scene> = Scene::replay(&boards)
+        .into_iter()
+        .zip(titles)
+        .map(|(scene, title)| scene.with_title(title))
+        .collect();
+
+    match flag(args, "--svg") {
+        Some(out) => {
+            let svg = if scenes.len() == 1 {
+                render::svg(&scenes[0])
+            } else {
+                render::svg_replay(&scenes)
+            };
+            fs::write(out, svg).map_err(|e| format!("{}: {}", out, e))?;
+            info!("Wrote {} positions to {}", scenes.len(), out);
+        }
+        None => {
+            let color = !args.iter().any(|a| a == "--plain");
+            for scene in &scenes {
+                println!("{}\n", render::terminal(scene, color));
+            }
+        }
+    }
+    Ok(())
+}
+
+// perft (--position pos.txt | --positions moves.jsonl) [--depth 4]
+fn perft(args: &[String]) -> i32 {
+    match count_positions(args) {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("{}", e);
+            1
+        }
+    }
+}
+
+fn count_positions(args: &[String]) -> Result<(), String> {
+    let depth = parsed_flag(args, "--depth", 4)?;
+    if depth < 1 {
+        return Err(format!("perft needs a --depth of at least 1, not {}", depth));
+    }
+    let boards = match (flag(args, "--position"), flag(args, "--positions")) {
+        (Some(path), _) => {
+            let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            let position: Position = text.parse().map_err(|e| format!("{}: {}", path, e))?;
+            vec![position.simple_board()]
+        }
+        (None, Some(path)) => read_positions(path)?.into_iter().map(|(b, _)| b).collect(),
+        (None, None) => return Err("perft needs --position or --positions".to_string()),
+    };
+
+    for board in &boards {
+        let start = Instant::now();
+        for d in 1..depth {
+            println!("perft({}) = {}", d, perft::perft(board, d));
+        }
+        let divided = perft::divide(board, depth);
+        for (moves, n) in &divided {
+            println!("{:<24} {}", joint_move(moves), n);
+        }
+        let total: u64 = divided.iter().map(|(_, n)| n).sum();
+        let elapsed = start.elapsed();
+        println!(
+            "perft({}) = {} in {} ms, {:.0} positions/s\n",
+            depth,
+            total,
+            elapsed.as_millis(),
+            total as f64 / elapsed.as_secs_f64().max(1e-9)
+        );
+    }
+    Ok(())
+}
+
+fn joint_move(moves: &JointMove) -> String {
+    let moves: Vec<String> = moves.iter().map(|m| format!("{}:{}", m.id, m.mv)).collect();
+    moves.join(" ")
+}
+
+#[derive(Deserialize)]
+struct SuitePosition {
+    name: String,
+    position: Vec<String>,
+}
+
+// bench [--suite fixtures/search.json] [--depth 5]
+fn bench(args: &[String]) -> i32 {
+    match compare_orderings(args) {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("{}", e);
+            1
+        }
+    }
+}
+
+// Node counts of a fixed depth search of every suite position with each move ordering
+fn compare_orderings(args: &[String]) -> Result<(), String> {
+    let path = flag(args, "--suite").unwrap_or("fixtures/search.json");
+    let depth = parsed_flag(args, "--depth", 5)?;
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let suite: Vec<SuitePosition> = serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path, e))?;
+
+    println!("{:<24}{}", "position", ORDERINGS.map(|o| format!("{:>12}", o)).concat());
+    let mut totals = [0; ORDERINGS.len()];
+    for p in &suite {
+        let position: Position = p.position.join("\n").parse().map_err(|e| format!("{}: {}", p.name, e))?;
+        let board = position.simple_board();
+        let mut row = format!("{:<24}", p.name);
+        for (i, ordering) in ORDERINGS.iter().enumerate() {
+            let nodes = search_depth(&board, depth, ordering).nodes;
+            totals[i] += nodes;
+            row += &format!("{:>12}", nodes);
+        }
+        println!("{}", row);
+    }
+    println!("{:<24}{}", "total", totals.map(|n| format!("{:>12}", n)).concat());
+    Ok(())
+}
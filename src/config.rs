@@ -0,0 +1,411 @@
+// Settings for the server, the search and the evaluation in one place.
+//
+// Values come from the defaults below, then Snake.toml (or the file named by
+// SNAKE_CONFIG), then environment variables like SNAKE_SEARCH__MAX_DEPTH=50 where a
+// double underscore separates the section from the key. PEER_URLS, PEER_TOKEN,
+// PEER_MARGIN_MS, SHOUT_INTENT and OPENING_BOOK are still read as short names for their
+// settings. The result is checked once at startup and is read from everywhere through
+// `config()`.
+
+use rocket::figment::providers::{Env, Format, Serialized, Toml};
+use rocket::figment::Figment;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::env;
+use std::sync::OnceLock;
+
+use crate::appearance::Appearance;
+use crate::logging;
+use crate::logic::ordering::ORDERINGS;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// Environment variables read under their own name and the setting each one stands for
+const LEGACY_NAMES: [(&str, &str); 5] = [
+    ("peer_urls", "team.peer_urls"),
+    ("peer_token", "team.peer_token"),
+    ("peer_margin_ms", "time.peer_margin_ms"),
+    ("shout_intent", "team.shout_intent"),
+    ("opening_book", "book.path"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+    // seconds, 0 closes the connection after every request
+    pub keep_alive: u32,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: "0.0.0.0".to_string(),
+            port: 8000,
+            keep_alive: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeConfig {
+    // Taken off the game timeout for the network round trip until the latency of our
+    // first answer is known
+    pub move_margin_ms: u32,
+    // Kept for exchanging plans with peer servers
+    pub peer_margin_ms: u32,
+}
+
+impl Default for TimeConfig {
+    fn default() -> Self {
+        TimeConfig {
+            move_margin_ms: 25,
+            peer_margin_ms: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    // children are sorted by a one ply evaluation, "history" breaks its ties with
+    // killers and the history table
+    pub ordering: String,
+    pub max_depth: i32,
+    // half width of the window around the previous iteration's value
+    pub aspiration_window: i32,
+    // nanoseconds a leaf evaluation is expected to take
+    pub heuristic_time_ns: i64,
+    // nanoseconds kept for unwinding the search
+    pub return_time_ns: i64,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            ordering: "history".to_string(),
+            max_depth: 100,
+            aspiration_window: 16,
+            heuristic_time_ns: 1,
+            return_time_ns: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weights {
+    // per body part of our snakes
+    pub length: i32,
+    // per body part of the strongest opposing team
+    pub opponent_length: i32,
+    // per snake that died, ours against us and theirs for us
+    pub death: i32,
+    // health below which every missing point counts against a snake
+    pub low_health: i32,
+    // per food one side reaches first
+    pub food_ownership: i32,
+    // per opponent without a safe move after our move
+    pub kill: i32,
+    // per opponent down to a single safe move
+    pub squeeze: i32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            length: 8,
+            opponent_length: 8,
+            death: 20,
+            low_health: 20,
+            food_ownership: 3,
+            kill: 60,
+            squeeze: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    // RUST_LOG overrides this when it is set
+    pub level: String,
+    // "text" or "json" with one object per line
+    pub format: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "info".to_string(),
+            format: "text".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplayConfig {
+    // Directory games are recorded to for the viewer at /replays, empty (the default)
+    // turns it off
+    pub dir: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BookConfig {
+    // Opening book loaded on startup, a missing file leaves the book empty
+    pub path: String,
+}
+
+impl Default for BookConfig {
+    fn default() -> Self {
+        BookConfig {
+            path: "book.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TeamConfig {
+    // Base urls of the servers of team mates to exchange plans with
+    #[serde(deserialize_with = "url_list")]
+    pub peer_urls: Vec<String>,
+    // Shared secret every peer request carries, required with peer_urls
+    pub peer_token: String,
+    // Shout the planned move to team mates, opponents can read it as well
+    #[serde(deserialize_with = "switch")]
+    pub shout_intent: bool,
+}
+
+// true or false, or 1 or 0 as in SHOUT_INTENT=1
+fn switch<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Switch {
+        Bool(bool),
+        Number(u8),
+    }
+    match Switch::deserialize(deserializer)? {
+        Switch::Bool(on) => Ok(on),
+        Switch::Number(0) => Ok(false),
+        Switch::Number(1) => Ok(true),
+        Switch::Number(n) => Err(D::Error::custom(format!("expected true, false, 1 or 0, not {}", n))),
+    }
+}
+
+// A list of urls, or a single string with the urls separated by commas
+fn url_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Urls {
+        List(Vec<String>),
+        Joined(String),
+    }
+    let urls = match Urls::deserialize(deserializer)? {
+        Urls::List(urls) => urls,
+        Urls::Joined(urls) => urls.split(',').map(str::to_string).collect(),
+    };
+    Ok(urls
+        .iter()
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty())
+        .collect())
+}
+
+pub const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub time: TimeConfig,
+    pub search: SearchConfig,
+    pub weights: Weights,
+    pub logging: LoggingConfig,
+    pub replays: ReplayConfig,
+    pub book: BookConfig,
+    pub team: TeamConfig,
+    pub appearance: Appearance,
+}
+
+impl Config {
+    pub fn figment() -> Figment {
+        let path = env::var("SNAKE_CONFIG").unwrap_or_else(|_| "Snake.toml".to_string());
+        Figment::from(Serialized::defaults(Config::default()))
+            .merge(Toml::file(path))
+            .merge(Env::raw().only(&LEGACY_NAMES.map(|(name, _)| name)).map(|name| {
+                let key = LEGACY_NAMES.iter().find(|(n, _)| name == n).map_or("", |(_, key)| key);
+                key.into()
+            }))
+            .merge(Env::prefixed("SNAKE_").split("__"))
+            // Short names for the appearance, SNAKE_COLOR instead of SNAKE_APPEARANCE__COLOR
+            .merge(
+                Env::prefixed("SNAKE_")
+                    .only(&["author", "color", "head", "tail", "version"])
+                    .map(|key| format!("appearance.{}", key).into()),
+            )
+    }
+
+    pub fn load(figment: &Figment) -> Result<Self, Vec<String>> {
+        let config: Config = figment
+            .extract()
+            .map_err(|e| e.into_iter().map(|e| e.to_string()).collect::<Vec<_>>())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Every problem with the settings, not just the first one
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if self.server.port == 0 {
+            errors.push("server.port must not be 0".to_string());
+        }
+        if !ORDERINGS.contains(&self.search.ordering.as_str()) {
+            errors.push(format!(
+                "search.ordering must be one of {:?}, not {:?}",
+                ORDERINGS, self.search.ordering
+            ));
+        }
+        if !(1..=100).contains(&self.search.max_depth) {
+            errors.push(format!("search.max_depth must be between 1 and 100, not {}", self.search.max_depth));
+        }
+        if self.search.aspiration_window < 1 {
+            errors.push(format!("search.aspiration_window must be positive, not {}", self.search.aspiration_window));
+        }
+        if self.search.heuristic_time_ns < 0 || self.search.return_time_ns < 0 {
+            errors.push("search.heuristic_time_ns and search.return_time_ns must not be negative".to_string());
+        }
+        if self.time.move_margin_ms >= 500 {
+            errors.push(format!("time.move_margin_ms must be below 500, not {}", self.time.move_margin_ms));
+        }
+        let w = &self.weights;
+        for (name, value) in [
+            ("length", w.length),
+            ("opponent_length", w.opponent_length),
+            ("death", w.death),
+            ("low_health", w.low_health),
+            ("food_ownership", w.food_ownership),
+            ("kill", w.kill),
+            ("squeeze", w.squeeze),
+        ] {
+            if !(0..=1000).contains(&value) {
+                errors.push(format!("weights.{} must be between 0 and 1000, not {}", name, value));
+            }
+        }
+        if !LEVELS.contains(&self.logging.level.to_lowercase().as_str()) {
+            errors.push(format!("logging.level must be one of {:?}, not {:?}", LEVELS, self.logging.level));
+        }
+        if !logging::FORMATS.contains(&self.logging.format.as_str()) {
+            errors.push(format!(
+                "logging.format must be one of {:?}, not {:?}",
+                logging::FORMATS, self.logging.format
+            ));
+        }
+        if let Some(url) = self.team.peer_urls.iter().find(|url| !url.starts_with("http://")) {
+            errors.push(format!("team.peer_urls must be plain http urls, not {:?}", url));
+        }
+        if !self.team.peer_urls.is_empty() && self.team.peer_token.is_empty() {
+            errors.push("team.peer_token must be set when team.peer_urls is".to_string());
+        }
+        let color = self.appearance.color.trim_start_matches('#');
+        if !self.appearance.color.starts_with('#')
+            || color.len() != 6
+            || !color.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            errors.push(format!("appearance.color must look like #e83d84, not {:?}", self.appearance.color));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Settings for Rocket, ROCKET_* variables still override the file
+    pub fn rocket(&self) -> Figment {
+        Figment::from(rocket::Config::default())
+            .merge(("address", &self.server.address))
+            .merge(("port", self.server.port))
+            .merge(("keep_alive", self.server.keep_alive))
+            .merge(Env::prefixed("ROCKET_").global())
+    }
+}
+
+// Makes the settings visible to `config()`, only the first call has an effect
+pub fn install(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+// The installed settings, or the defaults when nothing was installed as in tests
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use rocket::figment::providers::{Format, Serialized, Toml};
+    use rocket::figment::Figment;
+
+    fn figment(toml: &str) -> Figment {
+        Figment::from(Serialized::defaults(Config::default())).merge(Toml::string(toml))
+    }
+
+    #[test]
+    fn test_defaults_are_valid() {
+        assert_eq!(Config::load(&figment("")), Ok(Config::default()));
+        let shipped = Toml::file(concat!(env!("CARGO_MANIFEST_DIR"), "/Snake.toml"));
+        let config = Config::load(&Figment::from(Serialized::defaults(Config::default())).merge(shipped));
+        assert_eq!(config, Ok(Config::default()));
+    }
+
+    #[test]
+    fn test_partial_file() {
+        let config = Config::load(&figment("[search]\nmax_depth = 12\n[weights]\nkill = 100\n")).unwrap();
+        assert_eq!(config.search.max_depth, 12);
+        assert_eq!(config.search.return_time_ns, 10);
+        assert_eq!(config.weights.kill, 100);
+        assert_eq!(config.weights.length, 8);
+    }
+
+    #[test]
+    fn test_appearance() {
+        let config = Config::load(&figment("[appearance]\ncolor = \"#3366ff\"\nhead = \"smart-caterpillar\"\n")).unwrap();
+        assert_eq!(config.appearance.color, "#3366ff");
+        assert_eq!(config.appearance.head, "smart-caterpillar");
+        assert_eq!(config.appearance.tail, "coffee");
+        assert!(Config::load(&figment("[appearance]\ncolor = \"pink\"\n")).is_err());
+    }
+
+    #[test]
+    fn test_peer_urls() {
+        let config = Config::load(&figment(
+            "[team]\npeer_urls = \" http://10.0.0.2:8000/, http://10.0.0.3:8000\"\npeer_token = \"secret\"\n",
+        ))
+        .unwrap();
+        assert_eq!(config.team.peer_urls, vec!["http://10.0.0.2:8000", "http://10.0.0.3:8000"]);
+        let config = Config::load(&figment("[team]\npeer_urls = [\"http://10.0.0.2:8000\"]\npeer_token = \"secret\"\n"));
+        assert_eq!(config.unwrap().team.peer_urls, vec!["http://10.0.0.2:8000"]);
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let errors = Config::load(&figment(
+            "[search]\nordering = \"random\"\nmax_depth = 0\n[logging]\nlevel = \"loud\"\n",
+        ))
+        .unwrap_err();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].starts_with("search.ordering"));
+
+        let errors = Config::load(&figment("[team]\npeer_urls = [\"https://10.0.0.2:8000\"]\n")).unwrap_err();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors[1].starts_with("team.peer_token"));
+
+        // Typos in a section are reported instead of silently ignored
+        assert!(Config::load(&figment("[search]\nmax_dept = 5\n")).is_err());
+    }
+}
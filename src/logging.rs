@@ -0,0 +1,187 @@
+// Log output with the game, turn, snake and request every record belongs to.
+//
+// Request handlers open a `Context` scope on the thread that does the work, and every
+// record logged on that thread until the scope ends carries its fields. Key-value pairs
+// passed to the log macros, like `info!(depth = 4; "Search finished")`, become fields
+// of their own. With `logging.format = "json"` each record is one JSON object per line.
+
+use log::kv::{Error, Key, Source, Value as KvValue, VisitSource};
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const FORMATS: [&str; 2] = ["text", "json"];
+
+static NEXT_REQUEST: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static CONTEXT: RefCell<Option<Context>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Context {
+    pub game: String,
+    pub turn: i32,
+    pub snake: String,
+    pub request: u64,
+}
+
+impl Context {
+    // Context for a new request, numbered in the order requests arrive
+    pub fn request(game: &str, turn: i32, snake: &str) -> Self {
+        Context {
+            game: game.to_string(),
+            turn,
+            snake: snake.to_string(),
+            request: NEXT_REQUEST.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    // Attaches the context to the current thread until the returned guard is dropped
+    pub fn enter(self) -> Scope {
+        let previous = CONTEXT.with(|c| c.replace(Some(self)));
+        Scope { previous }
+    }
+
+    fn current() -> Option<Context> {
+        CONTEXT.with(|c| c.borrow().clone())
+    }
+}
+
+pub struct Scope {
+    previous: Option<Context>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CONTEXT.with(|c| c.replace(previous));
+    }
+}
+
+// Collects the key-value pairs of a record
+struct Fields(Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: KvValue<'kvs>) -> Result<(), Error> {
+        let value = if let Some(v) = value.to_i64() {
+            Value::from(v)
+        } else if let Some(v) = value.to_u64() {
+            Value::from(v)
+        } else if let Some(v) = value.to_f64() {
+            Value::from(v)
+        } else if let Some(v) = value.to_bool() {
+            Value::from(v)
+        } else {
+            Value::from(value.to_string())
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+// Fields of a record as they are written in JSON format
+pub fn record_fields(
+    level: log::Level,
+    target: &str,
+    message: &str,
+    context: Option<&Context>,
+    kvs: &dyn Source,
+) -> Map<String, Value> {
+    let mut fields = Map::new();
+    fields.insert("level".to_string(), Value::from(level.as_str()));
+    fields.insert("target".to_string(), Value::from(target));
+    fields.insert("msg".to_string(), Value::from(message));
+    if let Some(context) = context {
+        fields.insert("game".to_string(), Value::from(context.game.as_str()));
+        fields.insert("turn".to_string(), Value::from(context.turn));
+        fields.insert("snake".to_string(), Value::from(context.snake.as_str()));
+        fields.insert("request".to_string(), Value::from(context.request));
+    }
+    let mut extra = Fields(Map::new());
+    let _ = kvs.visit(&mut extra);
+    fields.extend(extra.0);
+    fields
+}
+
+// Sets up env_logger with the level from RUST_LOG and the given output format
+pub fn init(format: &str) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if format == "json" {
+        builder.format(|buf, record| {
+            let mut fields = Map::new();
+            fields.insert("ts".to_string(), Value::from(buf.timestamp_millis().to_string()));
+            fields.extend(record_fields(
+                record.level(),
+                record.target(),
+                &record.args().to_string(),
+                Context::current().as_ref(),
+                record.key_values(),
+            ));
+            writeln!(buf, "{}", Value::Object(fields))
+        });
+    } else {
+        builder.format(|buf, record| {
+            let mut line = format!("[{} {:<5} {}]", buf.timestamp(), record.level(), record.target());
+            if let Some(c) = Context::current() {
+                line += &format!(" game={} turn={} snake={} request={}", c.game, c.turn, c.snake, c.request);
+            }
+            line += &format!(" {}", record.args());
+            let mut extra = Fields(Map::new());
+            let _ = record.key_values().visit(&mut extra);
+            for (key, value) in extra.0 {
+                line += &format!(" {}={}", key, value);
+            }
+            writeln!(buf, "{}", line)
+        });
+    }
+    builder.init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{record_fields, Context};
+    use serde_json::json;
+
+    #[test]
+    fn test_context_scope() {
+        assert_eq!(Context::current(), None);
+        let outer = Context::request("g1", 3, "a");
+        {
+            let _outer = outer.clone().enter();
+            {
+                let _inner = Context::request("g2", 0, "b").enter();
+                assert_eq!(Context::current().unwrap().game, "g2");
+            }
+            assert_eq!(Context::current(), Some(outer));
+        }
+        assert_eq!(Context::current(), None);
+    }
+
+    #[test]
+    fn test_record_fields() {
+        let context = Context {
+            game: "g1".to_string(),
+            turn: 7,
+            snake: "a".to_string(),
+            request: 12,
+        };
+        let kvs: &[(&str, i64)] = &[("depth", 5), ("nodes", 1200)];
+        let fields = record_fields(log::Level::Info, "search", "Search finished", Some(&context), &kvs);
+        assert_eq!(
+            serde_json::Value::Object(fields),
+            json!({
+                "level": "INFO",
+                "target": "search",
+                "msg": "Search finished",
+                "game": "g1",
+                "turn": 7,
+                "snake": "a",
+                "request": 12,
+                "depth": 5,
+                "nodes": 1200,
+            })
+        );
+    }
+}
@@ -10,32 +10,53 @@
 // To get you started we've included code to prevent your Battlesnake from moving backwards.
 // For more info see docs.battlesnake.com
 
-mod mm_search;
+pub mod bots;
+pub mod emergency;
+pub mod endgame;
+pub mod food;
+pub mod latency;
+pub mod mm_search;
+pub mod opening;
+pub mod opponent;
+pub mod ordering;
+pub mod perft;
+pub mod shout;
 pub mod simple;
+pub mod tactics;
 
 use log::info;
 //use rand::seq::SliceRandom;
 use serde_json::{json, Value};
 use std::hash::{Hash, Hasher};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::appearance::Appearance;
+use crate::config::config;
+use crate::peer::{PeerPlan, Peers};
+use crate::replay;
+use opening::OpeningBook;
 use crate::{Battlesnake, Board, Coord, Game, GameInfo};
 
-use mm_search::search;
+use mm_search::{search, SearchResult};
+use shout::Intent;
+use simple::{Movement, SimpleBoard, SnakeMove};
 //use simple::{SimpleBoard, SimpleSnake};
 
 // info is called when you create your Battlesnake on play.battlesnake.com
 // and controls your Battlesnake's appearance
 // TIP: If you open your Battlesnake URL in a browser you should see this data
-pub fn info() -> Value {
+pub fn info(appearance: &Appearance) -> Value {
     info!("INFO");
 
-    return json!({
+    json!({
         "apiversion": "1",
-        "author": "Group 18", // TODO: Your Battlesnake Username
-        "color": "#e83d84", // TODO: Choose color
-        "head": "tiger-king", // TODO: Choose head
-        "tail": "coffee", // TODO: Choose tail
-    });
+        "author": appearance.author,
+        "color": appearance.color,
+        "head": appearance.head,
+        "tail": appearance.tail,
+        "version": appearance.version,
+    })
 }
 
 // start is called when your Battlesnake begins a game
@@ -53,14 +74,19 @@ pub fn end(_game: &Game, _turn: &i32, _board: &Board, _you: &Battlesnake) {
 // move is called on every turn and returns your next move
 // Valid moves are "up", "down", "left", or "right"
 // See https://docs.battlesnake.com/api/example-move for available data
+#[allow(clippy::too_many_arguments)]
 pub fn get_move(
     _game: &Game,
     turn: &i32,
     _board: &Board,
     you: &Battlesnake,
     game_info: &mut GameInfo,
+    peers: &Peers,
+    book: &OpeningBook,
+    received: Instant,
 ) -> Value {
     let my_id = you.id.clone();
+    game_info.latency.observe(&my_id, *turn, &you.latency, game_info.timeout);
     let team_idx = game_info
         .agent_ids
         .iter()
@@ -68,24 +94,168 @@ pub fn get_move(
         .expect("Agent ID not found");
 
     if game_info.agent_moves[team_idx].len() == *turn as usize + 1 {
-        return json!({ "move": game_info.agent_moves[team_idx][*turn as usize] });
+        let chosen = game_info.agent_moves[team_idx][*turn as usize];
+        record_turn(_game, *turn, _board, you, chosen, None);
+        return json!({ "move": chosen });
     }
 
-    let moves = search(_board, &game_info);
-
-    let teammate_id = game_info.agent_ids[1 - team_idx].clone();
+    game_info.peer_ids = peers.peer_ids(&_game.id);
+    game_info.intents = shout::teammate_intents(_board, you, game_info, *turn);
+    let team_ids = [game_info.agent_ids.clone(), game_info.peer_ids.clone()].concat();
+    game_info.opponents.observe(*turn, _board, &team_ids);
+    let simple_board = SimpleBoard::from(_board, game_info);
     let board_idx = _board.snakes.iter().position(|s| s.id == my_id).unwrap();
+    // Everything the shout needs but the follow up move, so nothing is left to work out
+    // once the search returns
+    let shouted = shout::enabled().then(|| {
+        // Food team mates on other servers are going for isn't raced for
+        let teammate_food = game_info
+            .intents
+            .iter()
+            .filter_map(|(id, intent)| Some((_board.snakes.iter().position(|s| &s.id == id)?, intent.food?)))
+            .collect();
+        let food = food::claims(&simple_board, &teammate_food).get(&board_idx).copied();
+        (food, simple_board.safe_move_count(board_idx) <= 1)
+    });
+    let answer_by = answer_by(game_info, received);
+    let book_moves = book.lookup(&simple_board, *turn);
+    let result = match book_moves {
+        Some(moves) => {
+            info!("Book move");
+            SearchResult {
+                moves,
+                ..SearchResult::default()
+            }
+        }
+        None => {
+            let search_by = answer_by - Duration::from_millis(peers.margin() as u64);
+            let result = search(&simple_board, _board, game_info, search_by);
+            if peers.enabled() {
+                exchange_plans(_game, *turn, _board, &my_id, result, peers, answer_by)
+            } else {
+                result
+            }
+        }
+    };
+    let moves = &result.moves;
+
     let chosen = moves.iter().find(|mv| mv.id == board_idx).unwrap().mv;
     game_info.agent_moves[team_idx].push(chosen);
-    let teammate_idx =  _board.snakes.iter().position(|s| s.id == teammate_id);
-    if !teammate_idx.is_none() {
-        game_info.agent_moves[1 - team_idx]
-            .push(moves.iter().find(|mv| mv.id == teammate_idx.unwrap()).unwrap().mv);
+    // store the moves for the team mates that share this server
+    for (agent_idx, agent_id) in game_info.agent_ids.iter().enumerate() {
+        if agent_idx == team_idx {
+            continue;
+        }
+        let teammate_idx = _board.snakes.iter().position(|s| &s.id == agent_id);
+        if let Some(mv) = teammate_idx.and_then(|t| moves.iter().find(|mv| mv.id == t)) {
+            game_info.agent_moves[agent_idx].push(mv.mv);
+        }
     }
 
-    info!("MOVE {}: {}", turn, chosen);
-    // store down for team mate
-    return json!({ "move": chosen });
+    info!(
+        chosen:% = chosen,
+        value = result.value,
+        depth = result.depth,
+        nodes = result.nodes,
+        elapsed_ms = received.elapsed().as_millis() as u64;
+        "MOVE {}", chosen
+    );
+    // Book and peer moves have no search behind them
+    let searched = (result.depth > 0).then_some(&result);
+    record_turn(_game, *turn, _board, you, chosen, searched);
+    let Some((food, cornered)) = shouted else {
+        return json!({ "move": chosen });
+    };
+    let next = result
+        .next
+        .as_ref()
+        .and_then(|next| next.iter().find(|mv| mv.id == board_idx))
+        .map(|mv| mv.mv);
+    let intent = Intent {
+        turn: *turn + 1,
+        next,
+        food,
+        danger: next.is_none() || cornered,
+    };
+    json!({ "move": chosen, "shout": intent.encode() })
+}
+
+// The replay is written in the background, the answer doesn't wait for the disk
+fn record_turn(game: &Game, turn: i32, board: &Board, you: &Battlesnake, chosen: Movement, result: Option<&SearchResult>) {
+    if config().replays.dir.is_empty() {
+        return;
+    }
+    let record = replay::Record {
+        turn,
+        snake: you.id.clone(),
+        board: board.clone(),
+        chosen: Some(chosen),
+        value: result.map(|r| r.value),
+        depth: result.map(|r| r.depth),
+    };
+    let game_id = game.id.clone();
+    thread::spawn(move || replay::record(&config().replays.dir, &game_id, &record));
+}
+
+// When the answer has to be sent, counted from the moment the request arrived
+fn answer_by(game_info: &GameInfo, received: Instant) -> Instant {
+    let deadline = game_info
+        .latency
+        .deadline(game_info.timeout, config().time.move_margin_ms);
+    received + Duration::from_millis(deadline as u64)
+}
+
+// Sends our plan to the peer servers and plays the best of the plans that arrive before
+// the move timeout, our own included
+fn exchange_plans(
+    game: &Game,
+    turn: i32,
+    board: &Board,
+    my_id: &str,
+    result: SearchResult,
+    peers: &Peers,
+    answer_by: Instant,
+) -> SearchResult {
+    let own_plan = PeerPlan {
+        game_id: game.id.clone(),
+        turn,
+        sender_id: my_id.to_string(),
+        moves: result
+            .moves
+            .iter()
+            .map(|mv| (board.snakes[mv.id].id.clone(), mv.mv))
+            .collect(),
+        value: result.value,
+    };
+    peers.send_plan(&own_plan);
+
+    let deadline = answer_by - Duration::from_millis(peers.margin() as u64 / 2);
+    let plans = peers.wait_for_plans(&game.id, turn, deadline);
+    if plans.is_empty() {
+        info!("No peer plan, playing own search");
+        return result;
+    }
+    // Every server picks from the same plans by the same rule, so they all play the
+    // same joint move once every plan arrived
+    let best = plans
+        .into_iter()
+        .filter(|plan| plan.better_than(&own_plan))
+        .reduce(|best, plan| if plan.better_than(&best) { plan } else { best });
+    let Some(plan) = best.filter(|plan| plan.moves.contains_key(my_id)) else {
+        return result;
+    };
+    info!(sender = plan.sender_id.as_str(), value = plan.value; "Playing peer plan");
+    let moves = board
+        .snakes
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, s)| plan.moves.get(&s.id).map(|&mv| SnakeMove { id: idx, mv }))
+        .collect();
+    SearchResult {
+        moves,
+        value: plan.value,
+        ..SearchResult::default()
+    }
 }
 
 impl Hash for Board {
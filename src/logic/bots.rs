@@ -0,0 +1,163 @@
+// Baseline strategies to spar against.
+//
+// Each bot answers from the request alone and keeps no state between turns, so one
+// server can play any number of them at once. They are served under /bots/<name>/ next to
+// the real snake.
+
+use rand::seq::SliceRandom;
+use serde_json::{json, Value};
+
+use super::endgame;
+use super::mm_search::search_position;
+use super::simple::{Movement, SimpleBoard};
+use crate::config::config;
+use crate::{Coord, GameInfo, GameState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bot {
+    // Any move that doesn't lose right away
+    RandomSafe,
+    // The safe move that gets closest to the nearest food
+    GreedyFood,
+    // The safe move into the largest open area
+    FloodFillMax,
+    // The minimax search of the real snake, without team mates or peers
+    Minimax,
+}
+
+impl Bot {
+    pub fn all() -> [Bot; 4] {
+        [Bot::RandomSafe, Bot::GreedyFood, Bot::FloodFillMax, Bot::Minimax]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Bot::RandomSafe => "random",
+            Bot::GreedyFood => "greedy",
+            Bot::FloodFillMax => "floodfill",
+            Bot::Minimax => "minimax",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Bot> {
+        Bot::all().iter().copied().find(|b| b.name() == name)
+    }
+
+    pub fn info(&self) -> Value {
+        let (color, head, tail) = match self {
+            Bot::RandomSafe => ("#9e9e9e", "default", "default"),
+            Bot::GreedyFood => ("#4caf50", "smile", "round-bum"),
+            Bot::FloodFillMax => ("#2196f3", "beluga", "curled"),
+            Bot::Minimax => ("#ff9800", "evil", "sharp"),
+        };
+        json!({
+            "apiversion": "1",
+            "author": format!("{} baseline", config().appearance.author),
+            "color": color,
+            "head": head,
+            "tail": tail,
+            "version": env!("CARGO_PKG_VERSION"),
+        })
+    }
+
+    pub fn choose(&self, state: &GameState) -> Movement {
+        let game_info = GameInfo::new(state.game.id.clone(), state.game.timeout, vec![state.you.id.clone()]);
+        let board = SimpleBoard::from(&state.board, &game_info);
+        let Some(idx) = state.board.snakes.iter().position(|s| s.id == state.you.id) else {
+            return Movement::Up;
+        };
+        let safe = board.safe_moves(idx);
+        let Some(&fallback) = safe.first() else {
+            return Movement::Up;
+        };
+        let head = state.you.head;
+        match self {
+            Bot::RandomSafe => *safe.choose(&mut rand::thread_rng()).unwrap(),
+            Bot::GreedyFood => safe
+                .iter()
+                .copied()
+                .min_by_key(|&mv| {
+                    let next = step(head, mv);
+                    board.food.iter().map(|f| distance(next, *f)).min().unwrap_or(0)
+                })
+                .unwrap_or(fallback),
+            Bot::FloodFillMax => {
+                let regions = endgame::regions(&board);
+                let area = |mv: Movement| {
+                    let next = step(head, mv);
+                    let region = regions[(next.y * 11 + next.x) as usize];
+                    regions.iter().filter(|&&r| r.is_some() && r == region).count()
+                };
+                safe.iter().copied().max_by_key(|&mv| area(mv)).unwrap_or(fallback)
+            }
+            Bot::Minimax => {
+                let time = state.game.timeout.saturating_sub(config().time.move_margin_ms);
+                let result = search_position(&board, time);
+                result
+                    .moves
+                    .iter()
+                    .find(|m| m.id == idx)
+                    .map_or(fallback, |m| m.mv)
+            }
+        }
+    }
+}
+
+fn step(c: Coord, mv: Movement) -> Coord {
+    match mv {
+        Movement::Up => Coord { x: c.x, y: c.y + 1 },
+        Movement::Down => Coord { x: c.x, y: c.y - 1 },
+        Movement::Left => Coord { x: c.x - 1, y: c.y },
+        Movement::Right => Coord { x: c.x + 1, y: c.y },
+        Movement::None => c,
+    }
+}
+
+fn distance(a: Coord, b: Coord) -> i32 {
+    (a.x - b.x).abs() + (a.y - b.y).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bot;
+    use crate::logic::simple::Movement;
+    use crate::GameState;
+
+    fn state(you: &str, food: &str) -> GameState {
+        serde_json::from_str(&format!(
+            r#"{{"game": {{"id": "g", "ruleset": {{}}, "timeout": 100}}, "turn": 3,
+            "board": {{"height": 11, "width": 11, "food": [{food}], "hazards": [], "snakes": [
+                {you},
+                {{"id": "b", "name": "b", "health": 90, "latency": "0", "shout": null,
+                  "head": {{"x": 9, "y": 9}}, "length": 3,
+                  "body": [{{"x": 9, "y": 9}}, {{"x": 9, "y": 8}}, {{"x": 9, "y": 7}}]}}]}},
+            "you": {you}}}"#,
+            you = you,
+            food = food
+        ))
+        .unwrap()
+    }
+
+    // Snake in the bottom left corner heading up, with the wall on two sides
+    const CORNER: &str = r#"{"id": "a", "name": "a", "health": 90, "latency": "0", "shout": null,
+        "head": {"x": 0, "y": 2}, "length": 3,
+        "body": [{"x": 0, "y": 2}, {"x": 0, "y": 1}, {"x": 0, "y": 0}]}"#;
+
+    #[test]
+    fn test_names() {
+        for bot in Bot::all() {
+            assert_eq!(Bot::from_name(bot.name()), Some(bot));
+        }
+        assert_eq!(Bot::from_name("nope"), None);
+    }
+
+    #[test]
+    fn test_bots_play_safe_moves() {
+        let state = state(CORNER, r#"{"x": 3, "y": 2}"#);
+        for bot in Bot::all() {
+            let mv = bot.choose(&state);
+            assert!(mv == Movement::Up || mv == Movement::Right, "{:?} played {:?}", bot, mv);
+        }
+        assert_eq!(Bot::GreedyFood.choose(&state), Movement::Right);
+    }
+}
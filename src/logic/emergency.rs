@@ -0,0 +1,151 @@
+// Answers for move requests the search fails on.
+//
+// A panic anywhere in get_move used to become an HTTP 500, and the engine then moves the
+// snake up. handle_move runs get_move through `guarded` instead, which logs the failure
+// with the whole request body so it can be replayed, and answers with a move worked out
+// from the request alone: the flood fill bot's move, or if even that fails, whichever
+// move keeps the head on the board and off the bodies with the most room around it, and
+// the search's last resort when there is no such move.
+
+use log::error;
+use serde_json::Value;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+use super::bots::Bot;
+use super::simple::{Movement, SimpleBoard};
+use crate::{Coord, GameInfo, GameState};
+
+// The answer of f, or the emergency move when f panics
+pub fn guarded<F: FnOnce() -> Value>(state: &GameState, f: F) -> Result<Value, Movement> {
+    let failure = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(response) => return Ok(response),
+        Err(payload) => reason(payload.as_ref()),
+    };
+    let chosen = emergency_move(state);
+    let body = serde_json::to_string(state).unwrap_or_default();
+    error!(failure = failure.as_str(), chosen:% = chosen, body = body.as_str(); "Move failed, playing {}", chosen);
+    Err(chosen)
+}
+
+fn reason(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+pub fn emergency_move(state: &GameState) -> Movement {
+    let on_board = state.board.snakes.iter().any(|s| s.id == state.you.id);
+    let flood_fill = panic::catch_unwind(|| Bot::FloodFillMax.choose(state));
+    match flood_fill {
+        Ok(mv) if on_board => mv,
+        _ => open_move(state),
+    }
+}
+
+// Checks nothing but the board edges and the snakes' bodies, tails excluded as they
+// move out of the way
+fn open_move(state: &GameState) -> Movement {
+    let board = &state.board;
+    let free = |c: Coord| {
+        (0..board.width).contains(&c.x)
+            && (0..board.height as i32).contains(&c.y)
+            && !board
+                .snakes
+                .iter()
+                .any(|s| s.body[..s.body.len().saturating_sub(1)].contains(&c))
+    };
+    let head = state.you.head;
+    let step = |c: Coord, mv: Movement| match mv {
+        Movement::Up => Coord { x: c.x, y: c.y + 1 },
+        Movement::Down => Coord { x: c.x, y: c.y - 1 },
+        Movement::Left => Coord { x: c.x - 1, y: c.y },
+        Movement::Right => Coord { x: c.x + 1, y: c.y },
+        Movement::None => c,
+    };
+    Movement::all()
+        .into_iter()
+        .filter(|&mv| free(step(head, mv)))
+        .max_by_key(|&mv| {
+            let next = step(head, mv);
+            Movement::all().into_iter().filter(|&m| free(step(next, m))).count()
+        })
+        .or_else(|| last_resort(state))
+        .unwrap_or(Movement::Up)
+}
+
+// Boxed in, the move the search would rank first among the ones that can't be survived
+// for sure, into a tail that may move on, or else off the board or out of a team mate's way
+fn last_resort(state: &GameState) -> Option<Movement> {
+    let mut board = state.board.clone();
+    if !board.snakes.iter().any(|s| s.id == state.you.id) {
+        board.snakes.push(state.you.clone());
+    }
+    let idx = board.snakes.iter().position(|s| s.id == state.you.id)?;
+    let game_info = GameInfo::new(state.game.id.clone(), state.game.timeout, vec![state.you.id.clone()]);
+    SimpleBoard::from(&board, &game_info).last_resort(idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{emergency_move, guarded, open_move};
+    use crate::logic::simple::Movement;
+    use crate::GameState;
+    use serde_json::json;
+
+    // Our snake on the left edge heading down next to an opponent, down is the only way
+    fn state(you_on_board: bool) -> GameState {
+        let you = json!({"id": "a", "name": "a", "health": 90, "latency": "0", "shout": null,
+            "head": {"x": 0, "y": 5}, "length": 3,
+            "body": [{"x": 0, "y": 5}, {"x": 0, "y": 6}, {"x": 0, "y": 7}]});
+        let wall = json!({"id": "b", "name": "b", "health": 90, "latency": "0", "shout": null,
+            "head": {"x": 1, "y": 4}, "length": 4,
+            "body": [{"x": 1, "y": 4}, {"x": 1, "y": 5}, {"x": 1, "y": 6}, {"x": 1, "y": 7}]});
+        let snakes = if you_on_board { vec![you.clone(), wall] } else { vec![wall] };
+        serde_json::from_value(json!({
+            "game": {"id": "g", "ruleset": {}, "timeout": 500}, "turn": 12,
+            "board": {"height": 11, "width": 11, "food": [], "hazards": [], "snakes": snakes},
+            "you": you
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_search_failure_plays_emergency_move() {
+        let state = state(true);
+        assert_eq!(guarded(&state, || json!({"move": "left"})), Ok(json!({"move": "left"})));
+        // Not up into our own neck like the engine would
+        assert_eq!(guarded(&state, || panic!("Agent ID not found")), Err(Movement::Down));
+    }
+
+    #[test]
+    fn test_emergency_move_without_us_on_the_board() {
+        assert_eq!(emergency_move(&state(false)), Movement::Down);
+    }
+
+    #[test]
+    fn test_boxed_in_plays_last_resort() {
+        // On the bottom edge between two opponents, with our neck above
+        let you = json!({"id": "a", "name": "a", "health": 90, "latency": "0", "shout": null,
+            "head": {"x": 5, "y": 0}, "length": 3,
+            "body": [{"x": 5, "y": 0}, {"x": 5, "y": 1}, {"x": 5, "y": 2}]});
+        let left = json!({"id": "b", "name": "b", "health": 90, "latency": "0", "shout": null,
+            "head": {"x": 4, "y": 0}, "length": 3,
+            "body": [{"x": 4, "y": 0}, {"x": 4, "y": 1}, {"x": 4, "y": 2}]});
+        let right = json!({"id": "c", "name": "c", "health": 90, "latency": "0", "shout": null,
+            "head": {"x": 6, "y": 0}, "length": 3,
+            "body": [{"x": 6, "y": 0}, {"x": 6, "y": 1}, {"x": 6, "y": 2}]});
+        let state: GameState = serde_json::from_value(json!({
+            "game": {"id": "g", "ruleset": {}, "timeout": 500}, "turn": 12,
+            "board": {"height": 11, "width": 11, "food": [], "hazards": [], "snakes": [you.clone(), left, right]},
+            "you": you
+        }))
+        .unwrap();
+        // Off the board rather than into a body
+        assert_eq!(open_move(&state), Movement::Down);
+    }
+}
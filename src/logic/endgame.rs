@@ -0,0 +1,423 @@
+// Endgame evaluation for boards that have split into separate regions.
+//
+// Once none of our snakes can reach a cell an opponent can reach, every snake just tries
+// to outlast the others in its own region, as long as the bodies between the regions
+// don't move out of the way before the side that runs out first is gone. The survival
+// solver searches for the longest path a snake can take through its region, moving its
+// own tail along and treating the bodies of the others as walls that shrink from the
+// tail end. Leaves of the search share most of their regions, so solved regions are
+// kept and looked up again.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use super::simple::{SimpleBoard, SimpleSnake};
+use crate::Coord;
+
+const SIZE: i32 = 11;
+// Surviving this many turns counts as safe, the region is big enough to stall in
+pub const SURVIVAL_LIMIT: u32 = 40;
+// Positions the survival search visits for a single snake
+const NODE_BUDGET: u32 = 4000;
+// Value of an endgame the longer lived team wins, on top of the survival difference
+pub const ENDGAME_WIN: i32 = 5000;
+// Solved regions kept per thread before the table starts over
+const SOLVED_SIZE: usize = 1 << 16;
+
+thread_local! {
+    static SOLVED: RefCell<HashMap<u64, Option<u32>>> = RefCell::new(HashMap::new());
+}
+
+const DIRECTIONS: [(i32, i32); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+fn in_bounds(c: Coord) -> bool {
+    (0..SIZE).contains(&c.x) && (0..SIZE).contains(&c.y)
+}
+
+fn cell(c: Coord) -> usize {
+    (c.y * SIZE + c.x) as usize
+}
+
+// Region id of every free cell, None for cells taken by a snake
+pub fn regions(board: &SimpleBoard) -> Vec<Option<usize>> {
+    regions_after(board, 0)
+}
+
+// Regions once the snakes have moved for `turns` turns, the cells their tails leave by
+// then count as free
+fn regions_after(board: &SimpleBoard, turns: u32) -> Vec<Option<usize>> {
+    let free_at = bodies(board, None);
+    let occupied: Vec<bool> = free_at.iter().map(|&t| t > turns).collect();
+    let mut region = vec![None; (SIZE * SIZE) as usize];
+    let mut next_id = 0;
+    for start in 0..(SIZE * SIZE) as usize {
+        if occupied[start] || region[start].is_some() {
+            continue;
+        }
+        let mut queue = VecDeque::from([start]);
+        region[start] = Some(next_id);
+        while let Some(i) = queue.pop_front() {
+            let c = Coord {
+                x: i as i32 % SIZE,
+                y: i as i32 / SIZE,
+            };
+            for (dx, dy) in DIRECTIONS {
+                let n = Coord {
+                    x: c.x + dx,
+                    y: c.y + dy,
+                };
+                if in_bounds(n) && !occupied[cell(n)] && region[cell(n)].is_none() {
+                    region[cell(n)] = Some(next_id);
+                    queue.push_back(cell(n));
+                }
+            }
+        }
+        next_id += 1;
+    }
+    region
+}
+
+// Regions the snake at idx can step into next turn
+fn reachable_regions(board: &SimpleBoard, region: &[Option<usize>], idx: usize) -> HashSet<usize> {
+    let Some(Some(snake)) = board.snakes.get(idx) else {
+        return HashSet::new();
+    };
+    let head = snake.head();
+    DIRECTIONS
+        .iter()
+        .map(|&(dx, dy)| Coord {
+            x: head.x + dx,
+            y: head.y + dy,
+        })
+        .filter(|&n| in_bounds(n))
+        .filter_map(|n| region[cell(n)])
+        .collect()
+}
+
+// True when no snake of ours can reach a region an opponent can reach
+pub fn isolated(board: &SimpleBoard) -> bool {
+    separated(board, 0)
+}
+
+// Whether the sides are still apart after `turns` turns, however the bodies between
+// them have moved on by then
+fn separated(board: &SimpleBoard, turns: u32) -> bool {
+    let region = regions_after(board, turns);
+    let ours: HashSet<usize> = board
+        .team()
+        .iter()
+        .flat_map(|&i| reachable_regions(board, &region, i))
+        .collect();
+    board
+        .opps()
+        .all(|i| reachable_regions(board, &region, i).is_disjoint(&ours))
+}
+
+// Longest number of turns, up to `limit`, the snake at idx survives on its own. None
+// when the node budget ran out before that was settled.
+pub fn survival(board: &SimpleBoard, idx: usize, limit: u32) -> Option<u32> {
+    let Some(Some(snake)) = board.snakes.get(idx) else {
+        return Some(0);
+    };
+    let free_at = walls(board, idx);
+    let key = region_key(snake, &free_at, &board.food, limit);
+    if let Some(solved) = SOLVED.with(|s| s.borrow().get(&key).copied()) {
+        return solved;
+    }
+    let mut search = Survival {
+        free_at,
+        food: board.food.clone(),
+        nodes: 0,
+        exhausted: false,
+        limit,
+    };
+    let mut body = snake.body().clone();
+    let longest = search.longest(&mut body, snake.health(), 0);
+    let solved = (longest >= limit || !search.exhausted).then_some(longest);
+    SOLVED.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.len() >= SOLVED_SIZE {
+            s.clear();
+        }
+        s.insert(key, solved);
+    });
+    solved
+}
+
+// Turn from which each cell taken by a snake other than the one at idx is free again
+fn walls(board: &SimpleBoard, idx: usize) -> [u32; (SIZE * SIZE) as usize] {
+    bodies(board, Some(idx))
+}
+
+fn bodies(board: &SimpleBoard, except: Option<usize>) -> [u32; (SIZE * SIZE) as usize] {
+    let mut free_at = [0u32; (SIZE * SIZE) as usize];
+    for (i, other) in board.snakes.iter().enumerate() {
+        let Some(other) = other else {
+            continue;
+        };
+        if Some(i) == except {
+            continue;
+        }
+        let len = other.body().len() as u32;
+        for (pos, &c) in other.body().iter().enumerate() {
+            if in_bounds(c) {
+                free_at[cell(c)] = free_at[cell(c)].max(len - pos as u32);
+            }
+        }
+    }
+    free_at
+}
+
+// Everything the survival search can see: the snake itself and the walls and food of
+// the cells it could reach before the limit, whatever happens elsewhere on the board
+fn region_key(snake: &SimpleSnake, free_at: &[u32], food: &[Coord], limit: u32) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (snake.body(), snake.health(), limit).hash(&mut hasher);
+    let mut turn = vec![None; (SIZE * SIZE) as usize];
+    let head = snake.head();
+    if in_bounds(head) {
+        turn[cell(head)] = Some(0);
+    }
+    let mut queue = VecDeque::from([(head, 0)]);
+    while let Some((c, t)) = queue.pop_front() {
+        for (dx, dy) in DIRECTIONS {
+            let n = Coord {
+                x: c.x + dx,
+                y: c.y + dy,
+            };
+            if t >= limit || !in_bounds(n) || turn[cell(n)].is_some() || free_at[cell(n)] > t + 1 {
+                continue;
+            }
+            turn[cell(n)] = Some(t + 1);
+            queue.push_back((n, t + 1));
+        }
+    }
+    for (i, _) in turn.iter().enumerate().filter(|(_, t)| t.is_some()) {
+        let c = Coord {
+            x: i as i32 % SIZE,
+            y: i as i32 / SIZE,
+        };
+        (i, free_at[i], food.contains(&c)).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+struct Survival {
+    free_at: [u32; (SIZE * SIZE) as usize],
+    food: Vec<Coord>,
+    nodes: u32,
+    // Whether running out of nodes cut any path short of the limit
+    exhausted: bool,
+    limit: u32,
+}
+
+impl Survival {
+    fn longest(&mut self, body: &mut VecDeque<Coord>, health: i32, turn: u32) -> u32 {
+        if turn >= self.limit {
+            return turn;
+        }
+        if self.nodes >= NODE_BUDGET {
+            self.exhausted = true;
+            return turn;
+        }
+        self.nodes += 1;
+        let head = body[0];
+        let mut best = turn;
+        for (dx, dy) in DIRECTIONS {
+            let next = Coord {
+                x: head.x + dx,
+                y: head.y + dy,
+            };
+            if !in_bounds(next) || self.free_at[cell(next)] > turn + 1 {
+                continue;
+            }
+            let eats = self.food.contains(&next);
+            let tail = *body.back().unwrap();
+            if !eats {
+                body.pop_back();
+            }
+            // The tail has already moved away unless the snake just grew
+            if body.contains(&next) || (health <= 1 && !eats) {
+                if !eats {
+                    body.push_back(tail);
+                }
+                continue;
+            }
+            body.push_front(next);
+            if eats {
+                self.food.retain(|f| f != &next);
+            }
+            let value = self.longest(body, if eats { 100 } else { health - 1 }, turn + 1);
+            body.pop_front();
+            if eats {
+                self.food.push(next);
+            } else {
+                body.push_back(tail);
+            }
+            best = best.max(value);
+            if best >= self.limit {
+                break;
+            }
+        }
+        best
+    }
+}
+
+// Evaluation of an isolated board from our point of view, None while the endgame is
+// undecided because both sides can stall for the whole survival limit, a survival
+// search ran out of nodes or the regions open up to each other before the side that
+// runs out first is gone
+pub fn evaluate(board: &SimpleBoard) -> Option<i32> {
+    let best = |snakes: Vec<usize>| -> Option<i32> {
+        let survivals = snakes
+            .into_iter()
+            .filter(|&i| matches!(board.snakes.get(i), Some(Some(_))))
+            .map(|i| survival(board, i, SURVIVAL_LIMIT).map(|t| t as i32))
+            .collect::<Option<Vec<i32>>>()?;
+        Some(survivals.into_iter().max().unwrap_or(-1))
+    };
+    let ours = best(board.team().to_vec())?;
+    let theirs = best(board.opps().collect())?;
+    if !separated(board, ours.min(theirs).max(0) as u32) {
+        return None;
+    }
+    match ours.cmp(&theirs) {
+        std::cmp::Ordering::Greater => Some(ENDGAME_WIN + ours - theirs),
+        std::cmp::Ordering::Less => Some(-ENDGAME_WIN + ours - theirs),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, isolated, region_key, survival, walls, ENDGAME_WIN};
+    use crate::logic::simple::{SimpleBoard, SimpleSnake};
+    use crate::Coord;
+
+    // An opponent whose body walls off the bottom row, with its head far away
+    fn wall() -> SimpleSnake {
+        let mut body = vec![];
+        for y in (1..=5).rev() {
+            body.push(Coord { x: 10, y });
+        }
+        for x in (0..10).rev() {
+            body.push(Coord { x, y: 1 });
+        }
+        for y in 2..=10 {
+            body.push(Coord { x: 0, y });
+        }
+        SimpleSnake::new(body, 100)
+    }
+
+    fn pocket_board() -> SimpleBoard {
+        let ours = SimpleSnake::new(
+            vec![Coord { x: 2, y: 0 }, Coord { x: 1, y: 0 }, Coord { x: 0, y: 0 }],
+            100,
+        );
+        SimpleBoard::new(vec![], vec![Some(ours), Some(wall())], vec![vec![0], vec![1]])
+    }
+
+    #[test]
+    fn test_partition_detected() {
+        assert!(isolated(&pocket_board()));
+
+        let ours = SimpleSnake::new(vec![Coord { x: 5, y: 5 }], 100);
+        let theirs = SimpleSnake::new(vec![Coord { x: 7, y: 5 }], 100);
+        let open = SimpleBoard::new(vec![], vec![Some(ours), Some(theirs)], vec![vec![0], vec![1]]);
+        assert!(!isolated(&open));
+    }
+
+    #[test]
+    fn test_regions_that_open_up_decide_nothing() {
+        let hungry = |board: &mut SimpleBoard| {
+            let ours = board.snakes[0].as_ref().unwrap().body().iter().copied().collect();
+            board.snakes[0] = Some(SimpleSnake::new(ours, 10));
+        };
+        let mut board = pocket_board();
+        hungry(&mut board);
+        assert!(evaluate(&board).is_some_and(|v| v < -ENDGAME_WIN + 10));
+
+        // The same wall with its tail at the end of the pocket, which opens it after
+        // five turns while our snake still lives
+        let mut board = pocket_board();
+        hungry(&mut board);
+        let reversed = wall().body().iter().rev().copied().collect();
+        board.snakes[1] = Some(SimpleSnake::new(reversed, 100));
+        assert!(isolated(&board));
+        assert_eq!(evaluate(&board), None);
+    }
+
+    #[test]
+    fn test_survival_in_pocket() {
+        let board = pocket_board();
+        // Eight free cells to the right, then the snake runs into the corner
+        assert_eq!(survival(&board, 0, 40), Some(8));
+        assert_eq!(survival(&board, 1, 40), Some(40));
+        assert_eq!(evaluate(&board), Some(-ENDGAME_WIN + 8 - 40));
+    }
+
+    #[test]
+    fn test_unsettled_survival_decides_nothing() {
+        // Starving in the big region takes every path through it to prove, far more
+        // than the node budget
+        let ours = SimpleSnake::new(
+            vec![Coord { x: 2, y: 0 }, Coord { x: 1, y: 0 }, Coord { x: 0, y: 0 }],
+            100,
+        );
+        let starving = SimpleSnake::new(wall().body().iter().copied().collect(), 30);
+        let board = SimpleBoard::new(vec![], vec![Some(ours), Some(starving)], vec![vec![0], vec![1]]);
+        assert_eq!(survival(&board, 0, 40), Some(8));
+        assert_eq!(survival(&board, 1, 40), None);
+        assert_eq!(evaluate(&board), None);
+    }
+
+    #[test]
+    fn test_other_regions_share_solutions() {
+        let board = pocket_board();
+        let key = |board: &SimpleBoard| {
+            region_key(board.snakes[0].as_ref().unwrap(), &walls(board, 0), &board.food, 40)
+        };
+        // Food far away in the region of the opponent is out of our reach
+        let mut fed = pocket_board();
+        fed.food.push(Coord { x: 5, y: 5 });
+        assert_eq!(key(&board), key(&fed));
+        fed.food.push(Coord { x: 6, y: 0 });
+        assert_ne!(key(&board), key(&fed));
+    }
+
+    #[test]
+    fn test_survival_follows_tail() {
+        // A snake of length four in a closed two by two loop can chase its tail forever
+        let ours = SimpleSnake::new(
+            vec![
+                Coord { x: 0, y: 0 },
+                Coord { x: 1, y: 0 },
+                Coord { x: 1, y: 1 },
+                Coord { x: 0, y: 1 },
+            ],
+            100,
+        );
+        // Walled in by an opponent that stays in place for longer than the limit
+        let mut walls = vec![
+            Coord { x: 0, y: 2 },
+            Coord { x: 1, y: 2 },
+            Coord { x: 2, y: 2 },
+            Coord { x: 2, y: 1 },
+        ];
+        for x in 2..=10 {
+            walls.push(Coord { x, y: 0 });
+        }
+        for y in 1..=10 {
+            walls.push(Coord { x: 10, y });
+        }
+        let board = SimpleBoard::new(
+            vec![],
+            vec![Some(ours), Some(SimpleSnake::new(walls, 100))],
+            vec![vec![0], vec![1]],
+        );
+        assert!(isolated(&board));
+        assert_eq!(survival(&board, 0, 20), Some(20));
+    }
+}
@@ -0,0 +1,241 @@
+// Food race planning.
+//
+// For every snake a breadth first search finds the turn it can first reach each food,
+// counting cells taken by bodies as free once the tail has moved off them. Each food goes
+// to the snake that gets there first, and within our team every snake claims a different
+// food so two team mates don't chase the same one while an opponent grows elsewhere. Food
+// a team mate on another server shouted it is going for stays with that team mate.
+
+use std::collections::{HashMap, VecDeque};
+
+use super::simple::SimpleBoard;
+use crate::config::config;
+use crate::Coord;
+
+const SIZE: i32 = 11;
+
+// Turn every snake can reach every food first, indexed [snake][food]. None for dead
+// snakes and food that can't be reached.
+pub fn arrival_times(board: &SimpleBoard) -> Vec<Vec<Option<u32>>> {
+    // Turn from which each cell is free again, bodies vacate from the tail end
+    let mut free_at = [0u32; (SIZE * SIZE) as usize];
+    for snake in board.snakes.iter().flatten() {
+        let len = snake.body().len() as u32;
+        for (pos, c) in snake.body().iter().enumerate() {
+            if in_bounds(*c) {
+                free_at[cell(*c)] = free_at[cell(*c)].max(len - pos as u32);
+            }
+        }
+    }
+    board
+        .snakes
+        .iter()
+        .map(|snake| match snake {
+            Some(snake) => {
+                let dist = distances(snake.head(), &free_at);
+                board
+                    .food
+                    .iter()
+                    .map(|&f| if in_bounds(f) { dist[cell(f)] } else { None })
+                    .collect()
+            }
+            None => vec![None; board.food.len()],
+        })
+        .collect()
+}
+
+fn distances(head: Coord, free_at: &[u32]) -> Vec<Option<u32>> {
+    let mut dist = vec![None; (SIZE * SIZE) as usize];
+    if !in_bounds(head) {
+        return dist;
+    }
+    dist[cell(head)] = Some(0);
+    let mut queue = VecDeque::from([(head, 0)]);
+    while let Some((c, t)) = queue.pop_front() {
+        for (dx, dy) in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
+            let n = Coord {
+                x: c.x + dx,
+                y: c.y + dy,
+            };
+            if !in_bounds(n) || dist[cell(n)].is_some() || free_at[cell(n)] > t + 1 {
+                continue;
+            }
+            dist[cell(n)] = Some(t + 1);
+            queue.push_back((n, t + 1));
+        }
+    }
+    dist
+}
+
+fn in_bounds(c: Coord) -> bool {
+    (0..SIZE).contains(&c.x) && (0..SIZE).contains(&c.y)
+}
+
+fn cell(c: Coord) -> usize {
+    (c.y * SIZE + c.x) as usize
+}
+
+// Snake index that wins the race to each food, the longest of the snakes that arrive
+// first. None when nobody reaches it or snakes of different teams tie for the longest.
+pub fn owners(board: &SimpleBoard, arrivals: &[Vec<Option<u32>>]) -> Vec<Option<usize>> {
+    let length = |i: usize| board.snakes[i].as_ref().map_or(0, |s| s.body().len());
+    (0..board.food.len())
+        .map(|f| {
+            let first = arrivals.iter().filter_map(|times| times[f]).min()?;
+            let racers: Vec<usize> = (0..arrivals.len())
+                .filter(|&i| arrivals[i][f] == Some(first))
+                .collect();
+            let longest = racers.iter().map(|&i| length(i)).max()?;
+            let mut winners = racers.into_iter().filter(|&i| length(i) == longest);
+            let winner = winners.next()?;
+            let contested = winners.any(|i| board.team_of(i) != board.team_of(winner));
+            (!contested).then_some(winner)
+        })
+        .collect()
+}
+
+// Food each snake of our team should go for. Races we win are handed out closest first
+// with at most one food per snake and one snake per food, after the food team mates
+// claimed in their shouts, which are keyed by snake index.
+pub fn claims(board: &SimpleBoard, shouted: &HashMap<usize, Coord>) -> HashMap<usize, Coord> {
+    let arrivals = arrival_times(board);
+    let owners = owners(board, &arrivals);
+    let mut races: Vec<(u32, usize, usize)> = Vec::new();
+    for &i in board.team() {
+        for (f, owner) in owners.iter().enumerate() {
+            let ours = owner.is_some_and(|o| board.team_of(o) == Some(0));
+            if let (true, Some(t)) = (ours, arrivals.get(i).and_then(|a| a[f])) {
+                races.push((t, i, f));
+            }
+        }
+    }
+    races.sort();
+    let mut claimed = HashMap::new();
+    let mut taken = vec![false; board.food.len()];
+    for (&i, food) in shouted {
+        let f = board.food.iter().position(|f| f == food);
+        if let (Some(f), true) = (f, board.team().contains(&i)) {
+            taken[f] = true;
+            claimed.insert(i, *food);
+        }
+    }
+    for (_, i, f) in races {
+        if taken[f] || claimed.contains_key(&i) {
+            continue;
+        }
+        taken[f] = true;
+        claimed.insert(i, board.food[f]);
+    }
+    claimed
+}
+
+// Evaluation term for the food we win races to minus the food opponents win
+pub fn ownership(board: &SimpleBoard) -> i32 {
+    let weight = config().weights.food_ownership;
+    let arrivals = arrival_times(board);
+    owners(board, &arrivals)
+        .into_iter()
+        .flatten()
+        .map(|o| if board.team_of(o) == Some(0) { weight } else { -weight })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{arrival_times, claims, owners, ownership};
+    use crate::config::config;
+    use crate::logic::simple::{SimpleBoard, SimpleSnake};
+    use crate::Coord;
+    use std::collections::HashMap;
+
+    fn snake(body: &[(i32, i32)]) -> Option<SimpleSnake> {
+        Some(SimpleSnake::new(body.iter().map(|&(x, y)| Coord { x, y }).collect(), 100))
+    }
+
+    #[test]
+    fn test_arrival_waits_for_tail() {
+        // The opponent's tail at (5,1) moves away after one turn
+        let board = SimpleBoard::new(
+            vec![Coord { x: 5, y: 0 }],
+            vec![
+                snake(&[(5, 2), (6, 2), (7, 2)]),
+                snake(&[(3, 1), (4, 1), (5, 1)]),
+            ],
+            vec![vec![0], vec![1]],
+        );
+        let arrivals = arrival_times(&board);
+        assert_eq!(arrivals[1][0], Some(3));
+        assert_eq!(arrivals[0][0], Some(2));
+    }
+
+    #[test]
+    fn test_teammates_claim_different_food() {
+        let board = SimpleBoard::new(
+            vec![Coord { x: 2, y: 5 }, Coord { x: 8, y: 5 }],
+            vec![
+                snake(&[(4, 5), (4, 4)]),
+                snake(&[(5, 5), (5, 4)]),
+                snake(&[(0, 10), (0, 9)]),
+            ],
+            vec![vec![0, 1], vec![2]],
+        );
+        let claimed = claims(&board, &HashMap::new());
+        assert_eq!(claimed.get(&0), Some(&Coord { x: 2, y: 5 }));
+        assert_eq!(claimed.get(&1), Some(&Coord { x: 8, y: 5 }));
+        assert_eq!(ownership(&board), 2 * config().weights.food_ownership);
+    }
+
+    #[test]
+    fn test_shouted_food_stays_with_teammate() {
+        // Each snake is closest to the food on its side
+        let board = SimpleBoard::new(
+            vec![Coord { x: 3, y: 5 }, Coord { x: 7, y: 5 }],
+            vec![
+                snake(&[(4, 5), (4, 4)]),
+                snake(&[(9, 5), (9, 4)]),
+                snake(&[(0, 10), (0, 9)]),
+            ],
+            vec![vec![0, 1], vec![2]],
+        );
+        assert_eq!(claims(&board, &HashMap::new()).get(&0), Some(&Coord { x: 3, y: 5 }));
+        // The team mate shouted it is heading for the same food, so ours takes the other
+        let shouted = HashMap::from([(1, Coord { x: 3, y: 5 })]);
+        let claimed = claims(&board, &shouted);
+        assert_eq!(claimed.get(&0), Some(&Coord { x: 7, y: 5 }));
+        assert_eq!(claimed.get(&1), Some(&Coord { x: 3, y: 5 }));
+        // Food that has been eaten since is ignored
+        let shouted = HashMap::from([(1, Coord { x: 5, y: 5 })]);
+        assert_eq!(claims(&board, &shouted).get(&0), Some(&Coord { x: 3, y: 5 }));
+    }
+
+    #[test]
+    fn test_lost_race_counts_against_us() {
+        let board = SimpleBoard::new(
+            vec![Coord { x: 9, y: 9 }],
+            vec![snake(&[(0, 0), (0, 1)]), snake(&[(8, 8), (8, 7)])],
+            vec![vec![0], vec![1]],
+        );
+        assert!(claims(&board, &HashMap::new()).is_empty());
+        assert_eq!(ownership(&board), -config().weights.food_ownership);
+    }
+
+    #[test]
+    fn test_longest_first_arrival_owns_food() {
+        // Both arrive after two moves, the longer opponent wins the head-to-head
+        let food = vec![Coord { x: 5, y: 5 }];
+        let short = snake(&[(5, 3), (5, 2)]);
+        let long = snake(&[(5, 7), (5, 8), (5, 9)]);
+        let board = SimpleBoard::new(food.clone(), vec![short.clone(), long], vec![vec![0], vec![1]]);
+        assert_eq!(owners(&board, &arrival_times(&board)), vec![Some(1)]);
+        assert_eq!(ownership(&board), -config().weights.food_ownership);
+
+        // Same length on both sides decides nothing
+        let equal = snake(&[(5, 7), (5, 8)]);
+        let board = SimpleBoard::new(food.clone(), vec![short.clone(), equal.clone()], vec![vec![0], vec![1]]);
+        assert_eq!(owners(&board, &arrival_times(&board)), vec![None]);
+
+        // Unless they are team mates
+        let board = SimpleBoard::new(food, vec![short, equal], vec![vec![0, 1]]);
+        assert_eq!(owners(&board, &arrival_times(&board)), vec![Some(0)]);
+    }
+}
@@ -0,0 +1,114 @@
+// Latency compensation from the round trip times the engine reports.
+//
+// Every request carries the latency of our previous answer in `you.latency`, measured by
+// the engine from sending the request to receiving our reply. Taking off the time we
+// spent on the answer ourselves leaves the network overhead. The search deadline keeps
+// the worst overhead of the last few turns as margin, so a fast link leaves more time for
+// the search and a spike keeps the margin up for a while. The margin never drops below
+// MIN_MARGIN_MS, a quiet link doesn't say much about the next turn under load.
+
+use log::warn;
+use std::collections::{HashMap, VecDeque};
+
+// Turns of overhead that are remembered
+const WINDOW: usize = 10;
+// Added on top of the worst overhead seen
+const PAD_MS: u32 = 5;
+// Least margin kept however fast the link has been
+const MIN_MARGIN_MS: u32 = 15;
+// Answers closer than this to the timeout are logged
+const NEAR_MISS_MS: u32 = 20;
+
+#[derive(Debug, Clone, Default)]
+pub struct LatencyTracker {
+    overheads: VecDeque<u32>,
+    // Turn and milliseconds of the last answer we gave for each of our snakes
+    answered: HashMap<String, (i32, u32)>,
+}
+
+impl LatencyTracker {
+    // Records how long we took to answer the request for turn `turn` of snake `id`
+    pub fn answered(&mut self, id: &str, turn: i32, elapsed_ms: u32) {
+        self.answered.insert(id.to_string(), (turn, elapsed_ms));
+    }
+
+    // Reads the latency the engine reported for our previous answer. Returns the network
+    // overhead when it could be worked out.
+    pub fn observe(&mut self, id: &str, turn: i32, latency: &str, timeout: u32) -> Option<u32> {
+        let latency: u32 = latency.trim().parse().ok().filter(|&l| l > 0)?;
+        if latency >= timeout {
+            warn!(
+                answered_turn = turn - 1, latency_ms = latency, timeout_ms = timeout;
+                "Answer arrived after the timeout"
+            );
+        } else if latency + NEAR_MISS_MS >= timeout {
+            warn!(
+                answered_turn = turn - 1, latency_ms = latency, timeout_ms = timeout;
+                "Answer arrived close to the timeout"
+            );
+        }
+        let &(answered_turn, elapsed) = self.answered.get(id)?;
+        if answered_turn != turn - 1 {
+            return None;
+        }
+        let overhead = latency.saturating_sub(elapsed);
+        self.overheads.push_back(overhead);
+        if self.overheads.len() > WINDOW {
+            self.overheads.pop_front();
+        }
+        Some(overhead)
+    }
+
+    // Milliseconds to keep for the network, `default` until a round trip has been measured
+    pub fn margin(&self, default: u32) -> u32 {
+        match self.overheads.iter().max() {
+            Some(&worst) => (worst + PAD_MS).max(MIN_MARGIN_MS),
+            None => default,
+        }
+    }
+
+    // Milliseconds the answer may take so it still arrives before the timeout, at least
+    // half the timeout however bad the link
+    pub fn deadline(&self, timeout: u32, default_margin: u32) -> u32 {
+        timeout.saturating_sub(self.margin(default_margin)).max(timeout / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LatencyTracker;
+
+    #[test]
+    fn test_deadline_follows_overhead() {
+        let mut tracker = LatencyTracker::default();
+        assert_eq!(tracker.observe("us", 0, "", 500), None);
+        assert_eq!(tracker.deadline(500, 25), 475);
+
+        // 440 ms of search and 448 ms round trip leaves 8 ms of network
+        tracker.answered("us", 0, 440);
+        assert_eq!(tracker.observe("us", 1, "448", 500), Some(8));
+        assert_eq!(tracker.deadline(500, 25), 485);
+
+        // A spike keeps the margin up for the following turns
+        tracker.answered("us", 1, 440);
+        assert_eq!(tracker.observe("us", 2, "490", 500), Some(50));
+        for turn in 3..8 {
+            tracker.answered("us", turn - 1, 440);
+            tracker.observe("us", turn, "446", 500);
+        }
+        assert_eq!(tracker.deadline(500, 25), 445);
+    }
+
+    #[test]
+    fn test_stale_answers_ignored() {
+        let mut tracker = LatencyTracker::default();
+        tracker.answered("us", 3, 100);
+        assert_eq!(tracker.observe("us", 7, "120", 500), None);
+        assert_eq!(tracker.observe("them", 4, "120", 500), None);
+        assert_eq!(tracker.margin(25), 25);
+        // Nothing but a huge margin still leaves half the time to search
+        tracker.answered("us", 7, 10);
+        tracker.observe("us", 8, "400", 500);
+        assert_eq!(tracker.deadline(500, 25), 250);
+    }
+}
@@ -1,186 +1,490 @@
+use crate::config::config;
 use crate::{Board, /*Coord,*/ GameInfo};
-use log::info;
-use std::time::Instant;
+use log::{debug, info};
+use std::time::{Duration, Instant};
 
-// Define a tree node that can have many children
-#[derive(Debug)]
-struct TreeNode {
-    value: i32,
-    children: Vec<TreeNode>,
+use crate::logic::simple::SimpleBoard;
+
+use super::opponent::{prune_replies, Tendency};
+use super::ordering::MoveOrdering;
+use super::tactics;
+use super::simple::JointMove;
+
+// Opponent nodes this close to the root use the opponent models to prune replies
+const MODEL_PRUNE_DEPTH: i32 = 3;
+
+// Moves searched past the depth of an iteration while a head-to-head or trap is about to
+// be decided, the leaf evaluation can't tell who comes out of those alive
+const MAX_EXTENSION: i32 = 2;
+
+// Best joint move for our team and the value the search gave it
+#[derive(Debug, Clone, Default)]
+pub struct SearchResult {
+    pub moves: JointMove,
+    pub value: i32,
+    // What our team plans to play on the turn after
+    pub next: Option<JointMove>,
+    // Deepest line the search reached and the number of nodes it visited
+    pub depth: i32,
+    pub nodes: u64,
+}
+
+// Values are kept between -WIN and WIN so they can be negated, a loss for our team
+// is reported as i32::MIN outside the search like the heuristic does
+const WIN: i32 = i32::MAX;
+// A line that ends the game is worth WIN less the plies it takes, so wins come as soon
+// and losses as late as they can. Everything past DECIDED is such a line, evaluations
+// stay below it.
+const DECIDED: i32 = WIN - 1000;
+
+fn decided(value: i32) -> bool {
+    value.saturating_abs() > DECIDED
+}
+
+// What every node of one search shares
+struct Context<'a> {
+    tendencies: &'a [Option<Tendency>],
+    max_depth: i32,
+    // Moves a line may go past the depth of an iteration
+    max_extension: i32,
+    // None sorts the children by their one ply heuristic instead
+    ordering: Option<MoveOrdering>,
+    aspiration_window: i32,
+    // When the search has to stop, less the time a leaf and unwinding take
+    deadline: Instant,
+    // Depth of the current iteration, the deepest line it reached and whether running
+    // out of time cut any line short of it
+    limit: i32,
+    depth: i32,
+    truncated: bool,
+    // Nodes visited by every iteration so far
+    nodes: u64,
 }
 
-impl TreeNode {
-    fn new(value: i32) -> Self {
-        TreeNode {
-            value,
-            children: Vec::new(),
+impl<'a> Context<'a> {
+    fn new(tendencies: &'a [Option<Tendency>], max_depth: i32, ordering: &str, deadline: Instant) -> Self {
+        let search = &config().search;
+        let margin = Duration::from_nanos((search.heuristic_time_ns + search.return_time_ns) as u64);
+        Context {
+            tendencies,
+            max_depth,
+            max_extension: MAX_EXTENSION,
+            ordering: (ordering == "history").then(MoveOrdering::default),
+            aspiration_window: search.aspiration_window,
+            deadline: deadline.checked_sub(margin).unwrap_or(deadline),
+            limit: 0,
+            depth: 0,
+            truncated: false,
+            nodes: 0,
         }
     }
+}
 
-    fn add_child(&mut self, child: TreeNode) {
-        //info!("Adding child with value: {}", child.value);
-        self.children.push(child);
+// Searches the position of a move request until deadline, simple_board is board as the
+// search sees it
+pub fn search(simple_board: &SimpleBoard, board: &Board, game_info: &GameInfo, deadline: Instant) -> SearchResult {
+    let tendencies = game_info.opponents.tendencies(board);
+    let mut simulations = simple_board.simulate_move(true);
+    // Team mates on other servers shouted the move they are about to make, only keep
+    // the joint moves that agree with them unless that leaves nothing to play
+    for (id, intent) in game_info.intents.iter().filter(|(_, i)| !i.danger) {
+        let (Some(mv), Some(idx)) = (intent.next, board.snakes.iter().position(|s| &s.id == id)) else {
+            continue;
+        };
+        let agrees = |m: &JointMove| m.iter().any(|s| s.id == idx && s.mv == mv);
+        if simulations.iter().any(|(m, _)| agrees(m)) {
+            simulations.retain(|(m, _)| agrees(m));
+        }
     }
+    let search = &config().search;
+    root_search(simulations, &mut Context::new(&tendencies, search.max_depth, &search.ordering, deadline))
+}
+
+// Searches a position without anything known about the game it comes from
+pub fn search_position(board: &SimpleBoard, time_limit: u32) -> SearchResult {
+    let search = &config().search;
+    let deadline = Instant::now() + Duration::from_millis(time_limit as u64);
+    root_search(board.simulate_move(true), &mut Context::new(&[], search.max_depth, &search.ordering, deadline))
+}
+
+// Searches a position to a fixed depth without a time limit, so the result and the
+// node count only depend on the position
+pub fn search_depth(board: &SimpleBoard, max_depth: i32, ordering: &str) -> SearchResult {
+    search_extended(board, max_depth, ordering, MAX_EXTENSION)
+}
+
+fn search_extended(board: &SimpleBoard, max_depth: i32, ordering: &str, max_extension: i32) -> SearchResult {
+    let deadline = Instant::now() + Duration::from_millis(u32::MAX as u64);
+    let mut ctx = Context::new(&[], max_depth, ordering, deadline);
+    ctx.max_extension = max_extension;
+    root_search(board.simulate_move(true), &mut ctx)
+}
+
+// Iterative deepening one turn at a time. Every iteration after the first searches a
+// window around the previous value and only opens the side it fails on, and starts
+// with the previous best move so the rest of the root can be searched with null windows.
+fn root_search(mut simulations: Vec<(JointMove, SimpleBoard)>, ctx: &mut Context) -> SearchResult {
+    let start = Instant::now();
 
-    fn print(&self, prefix: String, is_last: bool) {
-        println!(
-            "{}{}─ {}",
-            prefix,
-            if is_last { "└" } else { "├" },
-            self.value
-        );
-        let new_prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
+    // Moves that trap an opponent are searched first to raise the bound for the rest
+    simulations.sort_by_key(|(_, b)| -tactics::score(b));
+    for (move_pair, next_board) in &simulations {
+        for tactic in tactics::kills(next_board) {
+            info!(moves:? = move_pair, victim = tactic.victim, kind:? = tactic.kind; "Trap found");
+        }
+    }
 
-        let last_index = self.children.len().saturating_sub(1);
-        for (i, child) in self.children.iter().enumerate() {
-            child.print(new_prefix.clone(), i == last_index);
+    // Value and depth of the last finished iteration, its move is simulations[0]
+    let mut best: Option<(i32, i32)> = None;
+    let max_depth = ctx.max_depth;
+    for limit in (1..=max_depth).filter(|&d| d % 2 == 0 || d == max_depth) {
+        ctx.limit = limit;
+        let mut window = match best {
+            Some((value, _)) => (
+                value.saturating_sub(ctx.aspiration_window).max(-WIN),
+                value.saturating_add(ctx.aspiration_window),
+            ),
+            None => (-WIN, WIN),
+        };
+        let (idx, value) = loop {
+            let (idx, value) = root_iteration(&simulations, window, ctx);
+            if ctx.truncated {
+                break (idx, value);
+            }
+            if value <= window.0 && window.0 > -WIN {
+                window.0 = -WIN;
+            } else if value >= window.1 && window.1 < WIN {
+                window.1 = WIN;
+            } else {
+                break (idx, value);
+            }
+        };
+        debug!(depth = limit, value = value, truncated = ctx.truncated; "Iteration searched");
+        // An iteration that ran out of time is only used when there is nothing else
+        if ctx.truncated && best.is_some() {
+            break;
         }
+        simulations[..=idx].rotate_right(1);
+        best = Some((value, ctx.depth));
+        if ctx.truncated || decided(value) || ctx.depth < limit {
+            break;
+        }
+    }
+
+    let (value, depth) = best.expect("the first iteration is always kept");
+    let value = match value {
+        v if decided(v) && v < 0 => i32::MIN,
+        v if decided(v) => i32::MAX,
+        v => v,
+    };
+    info!(
+        moves:? = simulations[0].0,
+        value = value,
+        depth = depth,
+        nodes = ctx.nodes,
+        elapsed_ms = start.elapsed().as_millis() as u64;
+        "Search finished"
+    );
+    SearchResult {
+        moves: simulations[0].0.clone(),
+        value,
+        next: follow_up(&simulations[0].1),
+        depth,
+        nodes: ctx.nodes,
     }
 }
 
-use crate::logic::simple::SimpleBoard;
+// One pass over the root moves to ctx.limit, the index of the best one and its value
+fn root_iteration(
+    simulations: &[(JointMove, SimpleBoard)],
+    (mut alpha, beta): (i32, i32),
+    ctx: &mut Context,
+) -> (usize, i32) {
+    ctx.depth = 0;
+    ctx.truncated = false;
+    let mut best = (0, i32::MIN);
+    for (i, (move_pair, next_board)) in simulations.iter().enumerate() {
+        debug!(index = i; "Searching root move");
+        // The opponents move next, their values are negated
+        let mut value = i32::MIN;
+        if i > 0 {
+            value = -negamax(next_board, 1, false, (-alpha - 1, -alpha), (0, 0), ctx);
+        }
+        if i == 0 || (value > alpha && value < beta) {
+            value = -negamax(next_board, 1, false, (-beta, -alpha), (0, 0), ctx);
+        }
+        debug!(moves:? = move_pair, value = value; "Root move searched");
+        if value > best.1 {
+            best = (i, value);
+        }
+        alpha = alpha.max(value);
+        if alpha >= beta {
+            break;
+        }
+    }
+    best
+}
 
-use super::simple::SnakeMove;
+// Our best reply to the opponents' worst answer, a cheap guess at the next turn's move
+fn follow_up(board: &SimpleBoard) -> Option<JointMove> {
+    let replies = board.simulate_move(false);
+    let (_, worst) = replies.iter().min_by_key(|(_, b)| b.heuristic(true))?;
+    worst
+        .simulate_move(true)
+        .into_iter()
+        .max_by_key(|(_, b)| b.heuristic(true))
+        .map(|(m, _)| m)
+}
 
-pub fn search(board: &Board, game_info: &GameInfo) -> [SnakeMove; 2] {
-    let start = Instant::now();
-    let simple_board = SimpleBoard::from(board, game_info);
-    let timeout: i32 = game_info.timeout as i32 * 1_000_000; // Convert milliseconds to nanoseconds
-    let mut values = Vec::new();
-    let mut moves = Vec::new();
+// Lines end at the depth limit once the position is quiet or the extension is used up
+fn is_leaf(board: &SimpleBoard, depth: i32, limit: i32, max_extension: i32, our_team: bool) -> bool {
+    depth >= limit && (depth >= limit + max_extension || !tactics::imminent(board, our_team))
+}
 
-    let mut best_value = i32::MIN;
-    let simulations = simple_board.simulate_move(true);
-    for (i, (move_pair, next_board)) in simulations.iter().enumerate() {
-        let time: i32 = (timeout - start.elapsed().as_nanos() as i32) / (simulations.len() as i32 - i as i32);
-        info!("Move {} time: {} (timeout: {} elapsed: {})", i, time, timeout, start.elapsed().as_nanos());
-
-        let mut root = TreeNode::new(0);
-
-        // minmax on enemies since this outer loop is on friendly
-        let value = minmax_simple(
-            &next_board,
-            1,
-            false,
-            best_value,
-            i32::MAX,
-            1,
-            10,
-            time,
-            &mut root,
-        ).0;
-        //root.print(format!("{:?}:", move_pair), true);
-        info!("Move {:?} value: {}", move_pair, value);
-        best_value = best_value.max(value);
-        values.push(value);
-        moves.push(move_pair);
-    }
-    let idx = values
-        .iter()
-        .enumerate()
-        .max_by(|(_, v), (_, v2)| v.cmp(v2))
-        .map(|(i, _)| i)
-        .expect(&format!(
-            "No best move found in values: {:?} for {} moves",
-            values,
-            simulations.len()
-        ));
-    *moves[idx]
-}
-
-fn minmax_simple(
+// A value of our team from the point of view of the side to move
+fn relative(value: i32, our_team: bool) -> i32 {
+    let value = value.max(-WIN);
+    if our_team {
+        value
+    } else {
+        -value
+    }
+}
+
+// What a line ending on board after depth plies is worth to our team, the mean of the
+// evaluations along it. line holds the sum and number of the evaluations before the leaf.
+fn line_value(board: &SimpleBoard, our_team: bool, line: (i64, i64), depth: i32) -> i32 {
+    let mut h = board.heuristic(false);
+    if h == i32::MIN {
+        return -(WIN - depth);
+    }
+    if h == i32::MAX {
+        return WIN - depth;
+    }
+    // After our move the opponents still have to get out of any traps we set
+    if !our_team {
+        h = h.saturating_add(tactics::score(board));
+    }
+    ((line.0 + h as i64) / (line.1 + 1)).clamp(-DECIDED as i64, DECIDED as i64) as i32
+}
+
+// Negamax with principal variation search, values are from the point of view of the
+// side to move. A line is only valued at its leaf so every node is exactly the best of
+// its children, which the windows rely on.
+fn negamax(
     board: &SimpleBoard,
     depth: i32,
     our_team: bool,
-    mut alpha: i32,
-    mut beta: i32,
-    heuristic_time: i32,
-    return_time: i32,
-    timeout: i32,
-    parent: &mut TreeNode,
-) -> (i32, i32) {
-    let start = Instant::now();
-    let mut node = TreeNode::new(0);
-    if depth == 100 || heuristic_time + return_time >= timeout {
-        //info!("Depth {} reached", depth);
-        let h = board.heuristic(false);
-        node.value = h;
-        parent.add_child(node);
-        return (h, depth);
+    (mut alpha, beta): (i32, i32),
+    line: (i64, i64),
+    ctx: &mut Context,
+) -> i32 {
+    ctx.nodes += 1;
+    let h = board.heuristic(true);
+    let out_of_time = Instant::now() >= ctx.deadline;
+    if h == i32::MIN || h == i32::MAX || is_leaf(board, depth, ctx.limit, ctx.max_extension, our_team) || out_of_time {
+        ctx.depth = ctx.depth.max(depth);
+        ctx.truncated |= out_of_time && depth < ctx.limit;
+        return relative(line_value(board, our_team, line, depth), our_team);
     }
+    let line = (line.0 + h as i64, line.1 + 1);
 
     let mut simulations = board.simulate_move(our_team);
-    if our_team {
-        simulations.sort_by_key(|s| -s.1.heuristic(true));
-    } else {
-        simulations.sort_by_key(|s| s.1.heuristic(true));
+    if !our_team && depth <= MODEL_PRUNE_DEPTH {
+        prune_replies(board, ctx.tendencies, &mut simulations);
     }
-
-    if let Some(sim) = simulations.first() {
-        let h = sim.1.heuristic(true);
-        if our_team && h == i32::MAX {
-            //info!("Found max value at depth {}", depth);
-            node.value = i32::MAX;
-            parent.add_child(node);
-            return (i32::MAX, depth);
-        } else if !our_team && h == i32::MIN {
-            //info!("Found min value at depth {}", depth);
-            node.value = i32::MIN;
-            parent.add_child(node);
-            return (i32::MIN, depth);
+    match &ctx.ordering {
+        Some(ordering) => ordering.order(depth, &mut simulations, our_team),
+        // Kills and squeezes first, they're the moves most likely to cut off the search
+        None if our_team => {
+            simulations.sort_by_key(|s| -s.1.heuristic(true).saturating_add(tactics::score(&s.1)))
         }
+        None => simulations.sort_by_key(|s| s.1.heuristic(true)),
     }
 
-    let mut best_value = if our_team { (i32::MIN, depth) } else { (i32::MAX, depth)};
+    // A child that wins outright decides the node
+    let win = if our_team { i32::MAX } else { i32::MIN };
+    if simulations.iter().any(|s| s.1.heuristic(true) == win) {
+        ctx.depth = ctx.depth.max(depth + 1);
+        return WIN - (depth + 1);
+    }
 
-    for (idx, (_, next_board)) in simulations.iter().enumerate() {
-        let time_left = timeout - start.elapsed().as_nanos() as i32 - return_time;
-        if time_left <= heuristic_time {
-            best_value = (simulations.first().unwrap().1.heuristic(false), depth+1);
+    let mut best = i32::MIN;
+    for (idx, (joint_move, next_board)) in simulations.iter().enumerate() {
+        if Instant::now() >= ctx.deadline {
+            ctx.truncated = true;
+            if idx == 0 {
+                best = relative(line_value(next_board, !our_team, line, depth + 1), our_team);
+            }
             break;
         }
 
-        let iterations_left = simulations.len() as i32 - idx as i32;
-        let time_per_move = time_left / iterations_left;
-        let value = minmax_simple(
-            &next_board,
-            depth + 1,
-            !our_team,
-            alpha,
-            beta,
-            heuristic_time,
-            return_time,
-            time_per_move,
-            &mut node,
-        );
-        if our_team {
-            if (value.0 > best_value.0) || (value.0 == best_value.0 && value.1 > best_value.1) {
-                best_value = value;
-                alpha = alpha.max(best_value.0);
-                if best_value.0 >= beta {
-                    break;
-                }
+        // Everything after the first child only has to be shown worse than alpha
+        let mut value = i32::MIN;
+        if idx > 0 {
+            value = -negamax(next_board, depth + 1, !our_team, (-alpha - 1, -alpha), line, ctx);
+        }
+        if idx == 0 || (value > alpha && value < beta) {
+            value = -negamax(next_board, depth + 1, !our_team, (-beta, -alpha), line, ctx);
+        }
+        best = best.max(value);
+        alpha = alpha.max(value);
+        if alpha >= beta {
+            let remaining = ctx.limit - depth;
+            if let Some(ordering) = ctx.ordering.as_mut() {
+                ordering.cutoff(depth, joint_move, remaining);
             }
-        } else {
-            if (value.0 < best_value.0) || (value.0 == best_value.0 && value.1 > best_value.1) {
-                best_value = value;
-                beta = beta.max(best_value.0);
-                if best_value.0 <= alpha {
-                    break;
+            break;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_leaf, line_value, root_iteration, search_depth, search_extended, Context, DECIDED, MAX_EXTENSION, WIN,
+    };
+    use crate::logic::simple::{Movement, SimpleBoard};
+    use crate::notation::Position;
+    use serde::Deserialize;
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    #[derive(Deserialize)]
+    struct Fixture {
+        name: String,
+        position: Vec<String>,
+    }
+
+    fn suite() -> Vec<(String, SimpleBoard)> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/search.json");
+        let fixtures: Vec<Fixture> = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        fixtures
+            .into_iter()
+            .map(|f| (f.name, f.position.join("\n").parse::<Position>().unwrap().simple_board()))
+            .collect()
+    }
+
+    // Every child of every node to max_depth, what the windows must agree with
+    fn minimax(board: &SimpleBoard, depth: i32, our_team: bool, max_depth: i32, line: (i64, i64)) -> i32 {
+        let h = board.heuristic(true);
+        if h == i32::MIN || h == i32::MAX {
+            return h;
+        }
+        if is_leaf(board, depth, max_depth, MAX_EXTENSION, our_team) {
+            return line_value(board, our_team, line, depth);
+        }
+        let line = (line.0 + h as i64, line.1 + 1);
+        let values = board
+            .simulate_move(our_team)
+            .into_iter()
+            .map(|(_, b)| minimax(&b, depth + 1, !our_team, max_depth, line));
+        if our_team { values.max() } else { values.min() }.unwrap()
+    }
+
+    #[test]
+    fn test_same_values_as_minimax() {
+        for (name, board) in suite() {
+            for depth in 1..=3 {
+                let children = board.simulate_move(true);
+                let expected = children.iter().map(|(_, b)| minimax(b, 1, false, depth, (0, 0))).max().unwrap();
+                for ordering in ["history", "heuristic"] {
+                    let result = search_depth(&board, depth, ordering);
+                    assert_eq!(result.value, expected, "{} at depth {} with {} ordering", name, depth, ordering);
                 }
             }
         }
     }
 
-    //info!("Best value at depth {}: {}", depth, best_value);
-    node.value = best_value.0;
-    parent.add_child(node);
-    if best_value.0 == i32::MAX {
-        return best_value;
+    // Killers and history only reorder moves the one ply heuristic can't tell apart, so
+    // the values stay close while fewer nodes are searched over the suite
+    #[test]
+    fn test_history_ordering_searches_fewer_nodes() {
+        let (mut before, mut after) = (0, 0);
+        for (_, board) in suite() {
+            let heuristic = search_depth(&board, 4, "heuristic");
+            let history = search_depth(&board, 4, "history");
+            before += heuristic.nodes;
+            after += history.nodes;
+        }
+        assert!(after < before, "{} nodes with history ordering, {} without", after, before);
+    }
+
+    // Without a safe move the search used to play down, off the board here
+    #[test]
+    fn test_no_safe_move_takes_the_tail() {
+        let board = "
+            . . . . .
+            . . * . .
+            b b B . .
+            A a a . .
+        "
+        .parse::<Position>()
+        .unwrap()
+        .simple_board();
+        let result = search_depth(&board, 4, "history");
+        assert_eq!(result.moves[0].mv, Movement::Up);
+        assert!(result.value > i32::MIN);
+    }
+
+    // Both moves run into a dead end, the food only buys a longer body while the empty
+    // cells leave one more move to live
+    #[test]
+    fn test_losing_later_is_better() {
+        let board = "
+            . . . .
+            b b B .
+            b b . .
+            . b . .
+            . b b .
+            A * b .
+            a b b .
+            B: tail=1,0 stack=6
+        "
+        .parse::<Position>()
+        .unwrap()
+        .simple_board();
+        let result = search_depth(&board, 8, "history");
+        assert_eq!(result.moves[0].mv, Movement::Up);
+        assert_eq!(result.value, i32::MIN);
+
+        // Shallower iterations already prefer up, a single one that sees both losses
+        // has to tell them apart by itself
+        let mut simulations = board.simulate_move(true);
+        simulations.sort_by_key(|(m, _)| m[0].mv != Movement::Right);
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let mut ctx = Context::new(&[], 8, "history", deadline);
+        ctx.limit = 8;
+        let (idx, value) = root_iteration(&simulations, (-WIN, WIN), &mut ctx);
+        assert_eq!(simulations[idx].0[0].mv, Movement::Up);
+        assert!(value < -DECIDED);
+    }
+
+    #[derive(Deserialize)]
+    struct Tactical {
+        name: String,
+        depth: i32,
+        // Moves of our first snake that get out of or win the fight
+        moves: Vec<Movement>,
+        position: Vec<String>,
     }
-    if best_value.0 == i32::MIN {
-        return best_value;
+
+    // Every position is decided one turn past the search depth, where the leaf
+    // evaluation picks the wrong move without the extension
+    #[test]
+    fn test_extensions_see_past_the_depth() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/tactics.json");
+        let fixtures: Vec<Tactical> = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        for f in fixtures {
+            let board = f.position.join("\n").parse::<Position>().unwrap().simple_board();
+            let unextended = search_extended(&board, f.depth, "history", 0);
+            let ours = unextended.moves.iter().find(|m| m.id == 0).unwrap().mv;
+            assert!(!f.moves.contains(&ours), "{}: {} found without the extension", f.name, ours);
+            let result = search_depth(&board, f.depth, "history");
+            let ours = result.moves.iter().find(|m| m.id == 0).unwrap().mv;
+            assert!(f.moves.contains(&ours), "{}: played {} instead of one of {:?}", f.name, ours, f.moves);
+            assert!(result.depth > f.depth, "{}: no line was extended", f.name);
+        }
     }
-    let depth_diff = best_value.1 - depth;
-    ((best_value.0 * depth_diff + board.heuristic(true)) / (depth_diff+1), best_value.1)
 }
@@ -0,0 +1,379 @@
+// Opening book for the first turns of a game.
+//
+// Positions are keyed by a hash of their canonical form, the smallest of the eight
+// rotations and reflections of the board, so one entry covers every mirrored spawn. The
+// book is generated offline with long searches (see `cargo run -- book`) and loaded when
+// the server starts.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+
+use super::mm_search::search_position;
+use super::simple::{JointMove, Movement, SimpleBoard, SimpleSnake, SnakeMove};
+use crate::Coord;
+
+const SIZE: i32 = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symmetry {
+    swap: bool,
+    flip_x: bool,
+    flip_y: bool,
+}
+
+impl Symmetry {
+    pub fn all() -> Vec<Symmetry> {
+        let mut all = Vec::new();
+        for swap in [false, true] {
+            for flip_x in [false, true] {
+                for flip_y in [false, true] {
+                    all.push(Symmetry {
+                        swap,
+                        flip_x,
+                        flip_y,
+                    });
+                }
+            }
+        }
+        all
+    }
+
+    pub fn apply(&self, coord: Coord) -> Coord {
+        let (dx, dy) = self.apply_vector(coord.x, coord.y);
+        Coord {
+            x: if self.flip_x { SIZE - 1 + dx } else { dx },
+            y: if self.flip_y { SIZE - 1 + dy } else { dy },
+        }
+    }
+
+    fn apply_vector(&self, x: i32, y: i32) -> (i32, i32) {
+        let (x, y) = if self.swap { (y, x) } else { (x, y) };
+        (
+            if self.flip_x { -x } else { x },
+            if self.flip_y { -y } else { y },
+        )
+    }
+
+    pub fn apply_move(&self, mv: Movement) -> Movement {
+        let (dx, dy) = match mv {
+            Movement::Up => (0, 1),
+            Movement::Down => (0, -1),
+            Movement::Left => (-1, 0),
+            Movement::Right => (1, 0),
+            Movement::None => return Movement::None,
+        };
+        match self.apply_vector(dx, dy) {
+            (0, 1) => Movement::Up,
+            (0, -1) => Movement::Down,
+            (-1, 0) => Movement::Left,
+            _ => Movement::Right,
+        }
+    }
+
+    pub fn invert_move(&self, mv: Movement) -> Movement {
+        Movement::all()
+            .into_iter()
+            .find(|&m| self.apply_move(m) == mv)
+            .unwrap_or(Movement::None)
+    }
+}
+
+// Hash of the canonical form, the symmetry that produces it and our living snakes in
+// the order the book stores their moves
+struct Canonical {
+    key: String,
+    symmetry: Symmetry,
+    order: Vec<usize>,
+}
+
+fn body_string(snake: &SimpleSnake, symmetry: &Symmetry) -> String {
+    snake
+        .body()
+        .iter()
+        .map(|&c| {
+            let c = symmetry.apply(c);
+            format!("{},{}", c.x, c.y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn canonical(board: &SimpleBoard) -> Canonical {
+    let mut best: Option<(String, Symmetry, Vec<usize>)> = None;
+    for symmetry in Symmetry::all() {
+        let mut food: Vec<Coord> = board.food.iter().map(|&f| symmetry.apply(f)).collect();
+        food.sort_by_key(|f| (f.x, f.y));
+        let food: Vec<String> = food.iter().map(|f| format!("{},{}", f.x, f.y)).collect();
+
+        let living = |team: &[usize]| -> Vec<(String, usize)> {
+            let mut bodies: Vec<(String, usize)> = team
+                .iter()
+                .filter_map(|&i| {
+                    let snake = board.snakes.get(i)?.as_ref()?;
+                    Some((body_string(snake, &symmetry), i))
+                })
+                .collect();
+            bodies.sort();
+            bodies
+        };
+        let ours = living(board.team());
+        let mut theirs: Vec<String> = board
+            .teams()
+            .iter()
+            .skip(1)
+            .map(|team| {
+                let bodies: Vec<String> = living(team).into_iter().map(|(b, _)| b).collect();
+                bodies.join("|")
+            })
+            .filter(|team| !team.is_empty())
+            .collect();
+        theirs.sort();
+
+        let form = format!(
+            "F{}/T{}/O{}",
+            food.join(" "),
+            ours.iter().map(|(b, _)| b.as_str()).collect::<Vec<_>>().join("|"),
+            theirs.join("/")
+        );
+        if best.as_ref().is_none_or(|(b, _, _)| &form < b) {
+            best = Some((form, symmetry, ours.into_iter().map(|(_, i)| i).collect()));
+        }
+    }
+    let (form, symmetry, order) = best.unwrap();
+    Canonical {
+        key: format!("{:016x}", fnv1a(form.as_bytes())),
+        symmetry,
+        order,
+    }
+}
+
+// FNV-1a, stable between builds unlike the standard library hashers
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OpeningBook {
+    // Turns before this one are answered from the book
+    pub max_turn: i32,
+    entries: HashMap<String, Vec<Movement>>,
+}
+
+impl OpeningBook {
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        serde_json::from_str(&contents).map_err(std::io::Error::other)
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, serde_json::to_string(self).map_err(std::io::Error::other)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn insert(&mut self, board: &SimpleBoard, moves: &JointMove) {
+        let canonical = canonical(board);
+        let book_moves = canonical
+            .order
+            .iter()
+            .map(|&idx| {
+                let mv = moves.iter().find(|m| m.id == idx).map_or(Movement::None, |m| m.mv);
+                canonical.symmetry.apply_move(mv)
+            })
+            .collect();
+        self.entries.insert(canonical.key, book_moves);
+    }
+
+    // Book moves for our team, only if they are still a legal joint move on this board
+    pub fn lookup(&self, board: &SimpleBoard, turn: i32) -> Option<JointMove> {
+        if turn >= self.max_turn {
+            return None;
+        }
+        let canonical = canonical(board);
+        let book_moves = self.entries.get(&canonical.key)?;
+        let moves: JointMove = canonical
+            .order
+            .iter()
+            .zip(book_moves.iter())
+            .map(|(&idx, &mv)| SnakeMove {
+                id: idx,
+                mv: canonical.symmetry.invert_move(mv),
+            })
+            .collect();
+        let legal = board.simulate_move(true).into_iter().any(|(m, _)| same_moves(&m, &moves));
+        legal.then_some(moves)
+    }
+}
+
+fn same_moves(a: &JointMove, b: &JointMove) -> bool {
+    a.len() == b.len() && a.iter().all(|x| b.iter().any(|y| x.id == y.id && x.mv == y.mv))
+}
+
+// Food the standard rules may place next to a snake at the start: diagonal to the head,
+// further from the center on one axis and never in a corner
+fn start_food_options(head: Coord) -> Vec<Coord> {
+    let center = (SIZE - 1) / 2;
+    [(-1, -1), (-1, 1), (1, -1), (1, 1)]
+        .iter()
+        .map(|&(dx, dy)| Coord {
+            x: head.x + dx,
+            y: head.y + dy,
+        })
+        .filter(|p| !(p.x == center && p.y == center))
+        .filter(|p| {
+            (p.x < head.x && head.x < center)
+                || (center < head.x && head.x < p.x)
+                || (p.y < head.y && head.y < center)
+                || (center < head.y && head.y < p.y)
+        })
+        .filter(|p| !((p.x == 0 || p.x == SIZE - 1) && (p.y == 0 || p.y == SIZE - 1)))
+        .collect()
+}
+
+// Every 2v2 start position of the standard rules on an 11x11 board
+pub fn standard_starts() -> Vec<SimpleBoard> {
+    let corners = [(1, 1), (1, 9), (9, 1), (9, 9)];
+    let cardinals = [(1, 5), (5, 1), (5, 9), (9, 5)];
+    let mut boards = Vec::new();
+    for layout in [corners, cardinals] {
+        let heads: Vec<Coord> = layout.iter().map(|&(x, y)| Coord { x, y }).collect();
+        let snakes: Vec<Option<SimpleSnake>> = heads
+            .iter()
+            .map(|&h| Some(SimpleSnake::new(vec![h, h, h], 100)))
+            .collect();
+        // Every food combination, one food per snake plus the center
+        let mut foods: Vec<Vec<Coord>> = vec![vec![Coord { x: 5, y: 5 }]];
+        for &head in &heads {
+            foods = foods
+                .iter()
+                .flat_map(|f| {
+                    start_food_options(head).into_iter().map(move |option| {
+                        let mut next = f.clone();
+                        next.push(option);
+                        next
+                    })
+                })
+                .collect();
+        }
+        for partner in 1..4 {
+            let team = vec![0, partner];
+            let opps: Vec<usize> = (1..4).filter(|&i| i != partner).collect();
+            for food in &foods {
+                boards.push(SimpleBoard::new(
+                    food.clone(),
+                    snakes.clone(),
+                    vec![team.clone(), opps.clone()],
+                ));
+            }
+        }
+    }
+    boards
+}
+
+// Searches every position and, for books deeper than one turn, every answer the
+// opponents may give to the book move
+pub fn generate(starts: Vec<(SimpleBoard, i32)>, turns: i32, time_limit: u32) -> OpeningBook {
+    let mut book = OpeningBook {
+        max_turn: turns,
+        entries: HashMap::new(),
+    };
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from(starts);
+    while let Some((board, turn)) = queue.pop_front() {
+        if turn >= turns || !seen.insert(canonical(&board).key) {
+            continue;
+        }
+        let result = search_position(&board, time_limit);
+        book.insert(&board, &result.moves);
+        log::info!("Book position {} at turn {}: {:?}", book.len(), turn, result.moves);
+        if turn + 1 >= turns {
+            continue;
+        }
+        let ours = board.simulate_move(true);
+        let Some((_, after)) = ours.iter().find(|(m, _)| same_moves(m, &result.moves)) else {
+            continue;
+        };
+        for (_, reply) in after.simulate_move(false) {
+            queue.push_back((reply, turn + 1));
+        }
+    }
+    book
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical, standard_starts, OpeningBook, Symmetry};
+    use crate::logic::simple::{Movement, SimpleBoard, SimpleSnake, SnakeMove};
+    use crate::Coord;
+
+    fn mirrored(board: &SimpleBoard, symmetry: &Symmetry) -> SimpleBoard {
+        let snakes = board
+            .snakes
+            .iter()
+            .map(|s| {
+                s.as_ref().map(|s| {
+                    SimpleSnake::new(s.body().iter().map(|&c| symmetry.apply(c)).collect(), 100)
+                })
+            })
+            .collect();
+        let food = board.food.iter().map(|&f| symmetry.apply(f)).collect();
+        SimpleBoard::new(food, snakes, board.teams().to_vec())
+    }
+
+    #[test]
+    fn test_symmetry_moves() {
+        for symmetry in Symmetry::all() {
+            let head = Coord { x: 3, y: 4 };
+            for mv in Movement::all() {
+                let snake = SimpleSnake::new(vec![head], 100);
+                let moved = symmetry.apply(snake.next_position(mv));
+                let mirrored = SimpleSnake::new(vec![symmetry.apply(head)], 100);
+                assert_eq!(mirrored.next_position(symmetry.apply_move(mv)), moved);
+                assert_eq!(symmetry.invert_move(symmetry.apply_move(mv)), mv);
+            }
+        }
+    }
+
+    #[test]
+    fn test_standard_starts_reduce_by_symmetry() {
+        let starts = standard_starts();
+        assert_eq!(starts.len(), 96);
+        let mut keys: Vec<String> = starts.iter().map(|b| canonical(b).key).collect();
+        keys.sort();
+        keys.dedup();
+        assert!(keys.len() < starts.len());
+    }
+
+    #[test]
+    fn test_lookup_mirrored_position() {
+        let board = standard_starts().remove(0);
+        let moves = vec![
+            SnakeMove { id: 0, mv: Movement::Up },
+            SnakeMove { id: 1, mv: Movement::Down },
+        ];
+        let mut book = OpeningBook {
+            max_turn: 1,
+            ..Default::default()
+        };
+        book.insert(&board, &moves);
+
+        for symmetry in Symmetry::all() {
+            let looked_up = book.lookup(&mirrored(&board, &symmetry), 0).unwrap();
+            for m in &moves {
+                let found = looked_up.iter().find(|l| l.id == m.id).unwrap();
+                assert_eq!(found.mv, symmetry.apply_move(m.mv));
+            }
+        }
+        // Past the end of the book nothing is returned
+        assert!(book.lookup(&board, 1).is_none());
+    }
+}
@@ -0,0 +1,266 @@
+// Opponent modeling from the moves opponents have made earlier in the game.
+//
+// Each turn the previous board is compared with the new one to find the move every
+// opponent made, and the move is tagged with the tendencies it matches. Opponents that
+// keep playing the same way get their unlikely replies pruned near the root of the search.
+
+use std::collections::HashMap;
+
+use crate::logic::simple::{JointMove, Movement, SimpleBoard};
+use crate::{Board, Coord};
+
+// Moves seen before a model is trusted enough to prune with
+const MIN_OBSERVATIONS: u32 = 10;
+// Share of moves in percent that have to follow the dominant tendency
+const MIN_CONFIDENCE: u32 = 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tendency {
+    pub observations: u32,
+    // Percent of observed moves that went towards the closest food
+    pub food_greedy: u32,
+    // Percent of observed moves that went towards the closest head of another snake
+    pub aggressive: u32,
+    // Percent of observed moves that ended next to a wall
+    pub wall_hugging: u32,
+}
+
+impl Tendency {
+    pub fn confident(&self) -> bool {
+        self.observations >= MIN_OBSERVATIONS
+            && self.food_greedy.max(self.aggressive).max(self.wall_hugging) >= MIN_CONFIDENCE
+    }
+
+    // Relative likelihood of the snake at idx making the move, higher is more likely
+    pub fn score(&self, board: &SimpleBoard, idx: usize, mv: Movement) -> u32 {
+        let Some(Some(snake)) = board.snakes.get(idx) else {
+            return 0;
+        };
+        let from = snake.head();
+        let to = snake.next_position(mv);
+        let mut score = 1;
+        if closer(from, to, &board.food) {
+            score += self.food_greedy;
+        }
+        let heads: Vec<Coord> = board
+            .snakes
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| board.team_of(i) != board.team_of(idx))
+            .filter_map(|(_, s)| s.as_ref().map(|s| s.head()))
+            .collect();
+        if closer(from, to, &heads) {
+            score += self.aggressive;
+        }
+        if next_to_wall(to, 11, 11) {
+            score += self.wall_hugging;
+        }
+        score
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counts {
+    moves: u32,
+    food_greedy: u32,
+    aggressive: u32,
+    wall_hugging: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OpponentTracker {
+    last_turn: Option<i32>,
+    last_board: Option<Board>,
+    counts: HashMap<String, Counts>,
+}
+
+impl OpponentTracker {
+    // Records the move every snake not in `ignore` made since the previous turn
+    pub fn observe(&mut self, turn: i32, board: &Board, ignore: &[String]) {
+        if self.last_turn.is_some_and(|t| t >= turn) {
+            return;
+        }
+        if let Some(last) = &self.last_board {
+            for snake in board.snakes.iter().filter(|s| !ignore.contains(&s.id)) {
+                let Some(prev) = last.snakes.iter().find(|s| s.id == snake.id) else {
+                    continue;
+                };
+                let (from, to) = (prev.head, snake.head);
+                if (from.x - to.x).abs() + (from.y - to.y).abs() != 1 {
+                    continue;
+                }
+                let heads: Vec<Coord> = last
+                    .snakes
+                    .iter()
+                    .filter(|s| s.id != snake.id && (s.squad.is_empty() || s.squad != snake.squad))
+                    .map(|s| s.head)
+                    .collect();
+                let counts = self.counts.entry(snake.id.clone()).or_default();
+                counts.moves += 1;
+                counts.food_greedy += closer(from, to, &last.food) as u32;
+                counts.aggressive += closer(from, to, &heads) as u32;
+                counts.wall_hugging += next_to_wall(to, board.width, board.height as i32) as u32;
+            }
+        }
+        self.last_turn = Some(turn);
+        self.last_board = Some(board.clone());
+    }
+
+    pub fn tendency(&self, id: &str) -> Option<Tendency> {
+        let counts = self.counts.get(id)?;
+        let percent = |n: u32| n * 100 / counts.moves.max(1);
+        Some(Tendency {
+            observations: counts.moves,
+            food_greedy: percent(counts.food_greedy),
+            aggressive: percent(counts.aggressive),
+            wall_hugging: percent(counts.wall_hugging),
+        })
+    }
+
+    // Tendencies indexed like the snakes of a SimpleBoard built from the board
+    pub fn tendencies(&self, board: &Board) -> Vec<Option<Tendency>> {
+        board.snakes.iter().map(|s| self.tendency(&s.id)).collect()
+    }
+}
+
+// Drops the replies in which a confidently modelled snake plays anything but one of its
+// two most likely moves. Never drops every reply.
+pub fn prune_replies<T>(
+    board: &SimpleBoard,
+    tendencies: &[Option<Tendency>],
+    replies: &mut Vec<(JointMove, T)>,
+) {
+    let mut likely: HashMap<usize, Vec<Movement>> = HashMap::new();
+    for (m, _) in replies.iter() {
+        for snake_move in m {
+            let Some(Some(tendency)) = tendencies.get(snake_move.id) else {
+                continue;
+            };
+            if !tendency.confident() || likely.contains_key(&snake_move.id) {
+                continue;
+            }
+            let mut options: Vec<Movement> = replies
+                .iter()
+                .flat_map(|(m, _)| m.iter())
+                .filter(|s| s.id == snake_move.id)
+                .map(|s| s.mv)
+                .collect();
+            options.sort();
+            options.dedup();
+            options.sort_by_key(|&mv| std::cmp::Reverse(tendency.score(board, snake_move.id, mv)));
+            options.truncate(2);
+            likely.insert(snake_move.id, options);
+        }
+    }
+    if likely.is_empty() {
+        return;
+    }
+    let keep = |m: &JointMove| {
+        m.iter()
+            .all(|s| likely.get(&s.id).is_none_or(|options| options.contains(&s.mv)))
+    };
+    if replies.iter().any(|(m, _)| keep(m)) {
+        replies.retain(|(m, _)| keep(m));
+    }
+}
+
+fn distance(a: Coord, b: Coord) -> i32 {
+    (a.x - b.x).abs() + (a.y - b.y).abs()
+}
+
+// Whether stepping from `from` to `to` gets closer to the nearest of the targets
+fn closer(from: Coord, to: Coord, targets: &[Coord]) -> bool {
+    let nearest = targets.iter().min_by_key(|&&t| distance(from, t));
+    nearest.is_some_and(|&t| distance(to, t) < distance(from, t))
+}
+
+fn next_to_wall(coord: Coord, width: i32, height: i32) -> bool {
+    coord.x == 0 || coord.y == 0 || coord.x == width - 1 || coord.y == height - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prune_replies, OpponentTracker, Tendency};
+    use crate::logic::simple::{Movement, SimpleBoard, SimpleSnake};
+    use crate::{Battlesnake, Board, Coord};
+
+    fn api_snake(id: &str, body: Vec<Coord>) -> Battlesnake {
+        Battlesnake {
+            id: id.to_string(),
+            name: id.to_string(),
+            health: 100,
+            head: body[0],
+            length: body.len() as i32,
+            body,
+            latency: "0".to_string(),
+            shout: None,
+            squad: String::new(),
+        }
+    }
+
+    fn board(snakes: Vec<Battlesnake>, food: Vec<Coord>) -> Board {
+        Board {
+            height: 11,
+            width: 11,
+            food,
+            snakes,
+            hazards: vec![],
+        }
+    }
+
+    #[test]
+    fn test_observe_food_greedy() {
+        let mut tracker = OpponentTracker::default();
+        let food = vec![Coord { x: 10, y: 5 }];
+        for turn in 0..11 {
+            let snakes = vec![
+                api_snake("us", vec![Coord { x: 0, y: 0 }]),
+                api_snake("them", vec![Coord { x: turn, y: 5 }]),
+            ];
+            tracker.observe(turn, &board(snakes, food.clone()), &["us".to_string()]);
+        }
+        assert!(tracker.tendency("us").is_none());
+        let tendency = tracker.tendency("them").unwrap();
+        assert_eq!(tendency.observations, 10);
+        assert_eq!(tendency.food_greedy, 100);
+        assert!(tendency.confident());
+    }
+
+    #[test]
+    fn test_same_turn_observed_once() {
+        let mut tracker = OpponentTracker::default();
+        let first = board(vec![api_snake("them", vec![Coord { x: 5, y: 5 }])], vec![]);
+        let second = board(vec![api_snake("them", vec![Coord { x: 5, y: 6 }])], vec![]);
+        tracker.observe(0, &first, &[]);
+        tracker.observe(1, &second, &[]);
+        tracker.observe(1, &second, &[]);
+        assert_eq!(tracker.tendency("them").unwrap().observations, 1);
+    }
+
+    #[test]
+    fn test_prune_replies() {
+        let snakes = vec![
+            Some(SimpleSnake::from(&api_snake("us", vec![Coord { x: 0, y: 0 }]))),
+            Some(SimpleSnake::from(&api_snake("them", vec![Coord { x: 5, y: 5 }]))),
+        ];
+        let simple_board = SimpleBoard::new(vec![Coord { x: 5, y: 9 }], snakes, vec![vec![0], vec![1]]);
+        let greedy = Tendency {
+            observations: 20,
+            food_greedy: 90,
+            aggressive: 10,
+            wall_hugging: 0,
+        };
+
+        let mut replies = simple_board.simulate_move(false);
+        assert_eq!(replies.len(), 4);
+        prune_replies(&simple_board, &[None, Some(greedy)], &mut replies);
+        assert_eq!(replies.len(), 2);
+        assert!(replies.iter().any(|(m, _)| m[0].mv == Movement::Up));
+
+        // Unconfident models leave every reply in place
+        let mut replies = simple_board.simulate_move(false);
+        let unsure = Tendency { observations: 3, ..greedy };
+        prune_replies(&simple_board, &[None, Some(unsure)], &mut replies);
+        assert_eq!(replies.len(), 4);
+    }
+}
@@ -0,0 +1,100 @@
+// Move ordering from the cutoffs found elsewhere in the same search.
+//
+// A joint move that caused a cutoff is remembered as a killer for its depth, up to two
+// per depth, and each snake move in it earns history in proportion to the remaining
+// depth. Children are still sorted by the fast heuristic first, plus the trap score for
+// our team, because replacing it with killers and history alone searched more nodes on
+// fixtures/search.json. Many children share a heuristic value though, and ordering those
+// by killers and then history is what saves nodes (`cargo run --release -- bench`).
+
+use std::collections::HashMap;
+
+use super::simple::{JointMove, Movement, SimpleBoard};
+use super::tactics;
+
+pub const ORDERINGS: [&str; 2] = ["history", "heuristic"];
+
+const KILLERS_PER_DEPTH: usize = 2;
+
+#[derive(Debug, Default)]
+pub struct MoveOrdering {
+    killers: Vec<Vec<JointMove>>,
+    history: HashMap<(usize, Movement), i64>,
+}
+
+impl MoveOrdering {
+    fn killer(&self, depth: usize, m: &JointMove) -> i64 {
+        self.killers
+            .get(depth)
+            .and_then(|k| k.iter().position(|killer| killer == m))
+            .map_or(0, |slot| (KILLERS_PER_DEPTH - slot) as i64)
+    }
+
+    fn history(&self, m: &JointMove) -> i64 {
+        m.iter().map(|s| self.history.get(&(s.id, s.mv)).copied().unwrap_or(0)).sum()
+    }
+
+    // Sorts the children best first for the side to move
+    pub fn order(&self, depth: i32, simulations: &mut [(JointMove, SimpleBoard)], our_team: bool) {
+        let depth = depth as usize;
+        simulations.sort_by_cached_key(|(m, b)| {
+            let h = b.heuristic(true) as i64;
+            let h = if our_team { h + tactics::score(b) as i64 } else { -h };
+            (-h, -self.killer(depth, m), -self.history(m))
+        });
+    }
+
+    // Records the joint move that cut off the search at depth
+    pub fn cutoff(&mut self, depth: i32, m: &JointMove, remaining: i32) {
+        let d = depth as usize;
+        if self.killers.len() <= d {
+            self.killers.resize(d + 1, Vec::new());
+        }
+        let killers = &mut self.killers[d];
+        if !killers.contains(m) {
+            killers.insert(0, m.clone());
+            killers.truncate(KILLERS_PER_DEPTH);
+        }
+        let bonus = (remaining.max(1) as i64).pow(2);
+        for s in m {
+            *self.history.entry((s.id, s.mv)).or_insert(0) += bonus;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MoveOrdering;
+    use crate::logic::simple::{JointMove, Movement, SimpleBoard, SnakeMove};
+    use crate::notation::Position;
+
+    fn joint(moves: &[(usize, Movement)]) -> JointMove {
+        moves.iter().map(|&(id, mv)| SnakeMove { id, mv }).collect()
+    }
+
+    #[test]
+    fn test_killers_then_history_break_ties() {
+        // Left and right are mirror images, so only killers and history separate them
+        let board: SimpleBoard = "
+            . . . . .
+            . . A . .
+            . . . . .
+        "
+        .parse::<Position>()
+        .unwrap()
+        .simple_board();
+        let mut simulations = board.simulate_move(true);
+        let position = |simulations: &[(JointMove, SimpleBoard)], mv| simulations.iter().position(|(m, _)| m[0].mv == mv).unwrap();
+        let mut ordering = MoveOrdering::default();
+        // History from a cutoff at another depth, then a killer at this one
+        ordering.cutoff(5, &joint(&[(0, Movement::Left)]), 3);
+        ordering.cutoff(2, &joint(&[(0, Movement::Right)]), 1);
+
+        ordering.order(2, &mut simulations, true);
+        assert_eq!(position(&simulations, Movement::Right) + 1, position(&simulations, Movement::Left));
+
+        // Killers are per depth
+        ordering.order(3, &mut simulations, true);
+        assert_eq!(position(&simulations, Movement::Left) + 1, position(&simulations, Movement::Right));
+    }
+}
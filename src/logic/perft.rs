@@ -0,0 +1,80 @@
+// Counts of the positions the move generator reaches from a position, like perft in
+// chess engines. One ply is one side moving, our team first, the same way the search
+// alternates, so depth 2 is one full turn. Counts for a few positions are checked in
+// under fixtures/perft.json, a change to `simulate_move` or `get_safe_moves` that
+// changes them either fixes a bug or introduces one.
+
+use super::simple::{JointMove, SimpleBoard};
+
+// Number of positions exactly `depth` plies away
+pub fn perft(board: &SimpleBoard, depth: u32) -> u64 {
+    count(board, depth, true)
+}
+
+// The count below every joint move of our team at the root
+pub fn divide(board: &SimpleBoard, depth: u32) -> Vec<(JointMove, u64)> {
+    if depth == 0 {
+        return vec![];
+    }
+    board
+        .simulate_move(true)
+        .into_iter()
+        .map(|(m, next)| {
+            let n = count(&next, depth - 1, false);
+            (m, n)
+        })
+        .collect()
+}
+
+fn count(board: &SimpleBoard, depth: u32, our_team: bool) -> u64 {
+    match depth {
+        0 => 1,
+        1 => board.simulate_move(our_team).len() as u64,
+        _ => board
+            .simulate_move(our_team)
+            .iter()
+            .map(|(_, next)| count(next, depth - 1, !our_team))
+            .sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{divide, perft};
+    use crate::notation::Position;
+    use serde::Deserialize;
+    use std::fs;
+
+    #[derive(Deserialize)]
+    struct Fixture {
+        name: String,
+        position: Vec<String>,
+        // counts[i] is the perft count at depth i + 1
+        counts: Vec<u64>,
+    }
+
+    fn fixtures() -> Vec<Fixture> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/perft.json");
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_known_counts() {
+        for f in fixtures() {
+            let board = f.position.join("\n").parse::<Position>().unwrap().simple_board();
+            for (i, &expected) in f.counts.iter().enumerate() {
+                assert_eq!(perft(&board, i as u32 + 1), expected, "{} at depth {}", f.name, i + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_divide_sums_to_perft() {
+        for f in fixtures() {
+            let board = f.position.join("\n").parse::<Position>().unwrap().simple_board();
+            let depth = f.counts.len() as u32;
+            let total: u64 = divide(&board, depth).iter().map(|(_, n)| n).sum();
+            assert_eq!(total, perft(&board, depth), "{}", f.name);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+// Team signaling through the shout field of the move response.
+//
+// Every snake on the board can read the shouts of the others from the previous turn, so a
+// snake that shouts what it plans to do next lets team mates on other servers follow
+// along with one turn of delay. Opponents can read them as well, which is why this is
+// only turned on with team.shout_intent.
+
+use std::collections::HashMap;
+
+use crate::config::config;
+use crate::logic::simple::Movement;
+use crate::{Battlesnake, Board, Coord, GameInfo};
+
+// Marks shouts written by this snake, anything else is ignored
+const PREFIX: &str = "g18";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Intent {
+    // Turn the intent is meant for
+    pub turn: i32,
+    pub next: Option<Movement>,
+    pub food: Option<Coord>,
+    pub danger: bool,
+}
+
+impl Intent {
+    // g18:<turn>:<move or ->:<x,y or ->:<0 or 1>
+    pub fn encode(&self) -> String {
+        let next = self.next.map_or("-".to_string(), |mv| mv.to_string());
+        let food = self
+            .food
+            .map_or("-".to_string(), |f| format!("{},{}", f.x, f.y));
+        format!("{}:{}:{}:{}:{}", PREFIX, self.turn, next, food, self.danger as u8)
+    }
+
+    pub fn decode(shout: &str) -> Option<Self> {
+        let mut parts = shout.trim().split(':');
+        if parts.next()? != PREFIX {
+            return None;
+        }
+        let turn = parts.next()?.parse().ok()?;
+        let next = match parts.next()? {
+            "-" => None,
+            "up" => Some(Movement::Up),
+            "down" => Some(Movement::Down),
+            "left" => Some(Movement::Left),
+            "right" => Some(Movement::Right),
+            _ => return None,
+        };
+        let food = match parts.next()? {
+            "-" => None,
+            f => {
+                let (x, y) = f.split_once(',')?;
+                Some(Coord {
+                    x: x.parse().ok()?,
+                    y: y.parse().ok()?,
+                })
+            }
+        };
+        let danger = match parts.next()? {
+            "0" => false,
+            "1" => true,
+            _ => return None,
+        };
+        Some(Intent {
+            turn,
+            next,
+            food,
+            danger,
+        })
+    }
+}
+
+pub fn enabled() -> bool {
+    config().team.shout_intent
+}
+
+// Intents for this turn shouted by team mates that this server doesn't move itself
+pub fn teammate_intents(
+    board: &Board,
+    you: &Battlesnake,
+    game_info: &GameInfo,
+    turn: i32,
+) -> HashMap<String, Intent> {
+    board
+        .snakes
+        .iter()
+        .filter(|s| !game_info.agent_ids.contains(&s.id))
+        .filter(|s| {
+            game_info.peer_ids.contains(&s.id) || (!you.squad.is_empty() && s.squad == you.squad)
+        })
+        .filter_map(|s| {
+            let intent = Intent::decode(s.shout.as_deref()?)?;
+            (intent.turn == turn).then(|| (s.id.clone(), intent))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Intent;
+    use crate::logic::simple::Movement;
+    use crate::Coord;
+
+    #[test]
+    fn test_round_trip() {
+        let intent = Intent {
+            turn: 12,
+            next: Some(Movement::Left),
+            food: Some(Coord { x: 3, y: 10 }),
+            danger: true,
+        };
+        assert_eq!(intent.encode(), "g18:12:left:3,10:1");
+        assert_eq!(Intent::decode(&intent.encode()), Some(intent));
+
+        let quiet = Intent {
+            turn: 0,
+            next: None,
+            food: None,
+            danger: false,
+        };
+        assert_eq!(Intent::decode(&quiet.encode()), Some(quiet));
+    }
+
+    #[test]
+    fn test_ignores_other_shouts() {
+        assert_eq!(Intent::decode("hello there"), None);
+        assert_eq!(Intent::decode("g18:1:sideways:-:0"), None);
+        assert_eq!(Intent::decode("g18:1:up"), None);
+    }
+}
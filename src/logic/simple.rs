@@ -1,9 +1,9 @@
-//use rocket::futures::future::ok;
-
-//use crate::logic::{collision_with_body, collision_with_snakes, get_safe_moves, out_of_bounds};
+use crate::config::config;
+use crate::logic::{endgame, food};
+use crate::render;
 use crate::{Battlesnake, Board, Coord, GameInfo};
 use log::info;
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::cell::Cell;
 use std::collections::{HashMap, VecDeque};
 use std::fmt::{Debug, Display};
@@ -37,7 +37,7 @@ impl PartialOrd for Movement {
 }
 
 impl Movement {
-    fn to_order(&self) -> u8 {
+    fn to_order(self) -> u8 {
         match self {
             Movement::Up => 0,
             Movement::Down => 1,
@@ -74,8 +74,23 @@ impl Serialize for Movement {
         serializer.serialize_str(&str)
     }
 }
+impl<'de> Deserialize<'de> for Movement {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let str = String::deserialize(deserializer)?;
+        match str.as_str() {
+            "up" => Ok(Movement::Up),
+            "down" => Ok(Movement::Down),
+            "left" => Ok(Movement::Left),
+            "right" => Ok(Movement::Right),
+            _ => Err(serde::de::Error::custom(format!("unknown move {}", str))),
+        }
+    }
+}
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct SnakeMove {
     pub id: usize,
     pub mv: Movement,
@@ -87,130 +102,192 @@ impl Debug for SnakeMove {
     }
 }
 
+// One move for every living snake on the side that is moving
+pub type JointMove = Vec<SnakeMove>;
+
 #[derive(Debug, Clone)]
 pub struct SimpleBoard {
     pub food: Vec<Coord>,
     pub snakes: Vec<Option<SimpleSnake>>,
-    team: [usize; 2],
-    opps: [usize; 2],
+    // teams[0] is our team, every other entry is an opposing team
+    teams: Vec<Vec<usize>>,
     pub stored_fast_heuristic: Cell<Option<i32>>,
     pub stored_flood_fill_heuristic: Cell<Option<i32>>,
 }
 impl SimpleBoard {
-    pub fn from(board: &Board, game_info: &GameInfo) -> Self {
-        let mut simple_board = SimpleBoard {
-            food: board.food.clone(),
-            snakes: Vec::new(),
-            team: [10; 2],
-            opps: [10; 2],
+    pub fn new(food: Vec<Coord>, snakes: Vec<Option<SimpleSnake>>, teams: Vec<Vec<usize>>) -> Self {
+        SimpleBoard {
+            food,
+            snakes,
+            teams,
             stored_fast_heuristic: Cell::new(None),
             stored_flood_fill_heuristic: Cell::new(None),
-        };
-        let mut friendly_count = 0;
-        let mut enemy_count = 0;
+        }
+    }
+
+    pub fn from(board: &Board, game_info: &GameInfo) -> Self {
+        // Our team also holds squad mates and peer snakes that are played by another server
+        let our_squad = board
+            .snakes
+            .iter()
+            .find(|s| game_info.agent_ids.contains(&s.id))
+            .map(|s| s.squad.as_str())
+            .filter(|squad| !squad.is_empty());
+        let mut snakes = Vec::new();
+        let mut team = Vec::new();
+        let mut opp_teams: Vec<Vec<usize>> = Vec::new();
+        let mut opp_squads: Vec<&str> = Vec::new();
         for (idx, snake) in board.snakes.iter().enumerate() {
-            simple_board.snakes.push(Some(SimpleSnake::from(snake)));
-            if game_info.agent_ids.contains(&snake.id) {
-                simple_board.team[friendly_count] = idx;
-                friendly_count += 1;
-            } else {
-                simple_board.opps[enemy_count] = idx;
-                enemy_count += 1;
+            snakes.push(Some(SimpleSnake::from(snake)));
+            if game_info.agent_ids.contains(&snake.id)
+                || game_info.peer_ids.contains(&snake.id)
+                || our_squad == Some(snake.squad.as_str())
+            {
+                team.push(idx);
+                continue;
+            }
+            // Opponents without a squad are modelled as teams of their own
+            let squad_idx = opp_squads
+                .iter()
+                .position(|&squad| !squad.is_empty() && squad == snake.squad);
+            match squad_idx {
+                Some(t) => opp_teams[t].push(idx),
+                None => {
+                    opp_squads.push(&snake.squad);
+                    opp_teams.push(vec![idx]);
+                }
             }
         }
-        while friendly_count < 2 {
-            simple_board.team[friendly_count] = simple_board.snakes.len();
-            simple_board.snakes.push(None);
-            friendly_count += 1;
+        // Agents that are no longer on the board are kept as dead team members
+        while team.len() < game_info.agent_ids.len() {
+            team.push(snakes.len());
+            snakes.push(None);
         }
-        while enemy_count < 2 {
-            simple_board.opps[enemy_count] = simple_board.snakes.len();
-            simple_board.snakes.push(None);
-            enemy_count += 1;
+        let mut teams = vec![team];
+        teams.append(&mut opp_teams);
+        SimpleBoard::new(board.food.clone(), snakes, teams)
+    }
+
+    pub fn team(&self) -> &[usize] {
+        &self.teams[0]
+    }
+
+    pub fn opps(&self) -> impl Iterator<Item = usize> + '_ {
+        self.teams.iter().skip(1).flatten().copied()
+    }
+
+    pub fn teams(&self) -> &[Vec<usize>] {
+        &self.teams
+    }
+
+    // A board without opposing teams, as in a solo game
+    pub fn is_solo(&self) -> bool {
+        self.teams.len() < 2
+    }
+
+    // Index into teams for the snake at idx
+    pub fn team_of(&self, idx: usize) -> Option<usize> {
+        self.teams.iter().position(|t| t.contains(&idx))
+    }
+
+    // Snakes that move together when it is the given side's turn
+    fn side(&self, our_team: bool) -> Vec<usize> {
+        if our_team {
+            self.team().to_vec()
+        } else {
+            self.opps().collect()
         }
-        simple_board
     }
 
-    // fn evaluate_team(&self, our_team: bool) -> usize {
-    //     let mut v = 0;
-    //     for snake in self.snakes.iter() {
-    //         if snake.our_team == our_team {
-    //             v += snake.evaluate_value();
-    //         }
-    //     }
-    //     v
-    // }
+    // Number of safe moves for the snake at idx, zero for dead snakes
+    pub fn safe_move_count(&self, idx: usize) -> usize {
+        self.safe_moves(idx).len()
+    }
+
+    pub fn safe_moves(&self, idx: usize) -> Vec<Movement> {
+        let our_team = self.team_of(idx) == Some(0);
+        self.snakes
+            .get(idx)
+            .and_then(|s| s.as_ref())
+            .map_or(vec![], |s| s.get_safe_moves(self, our_team))
+    }
+
+    // The move the search falls back to for the snake at idx once it has no safe move
+    pub fn last_resort(&self, idx: usize) -> Option<Movement> {
+        let our_team = self.team_of(idx) == Some(0);
+        let snake = self.snakes.get(idx)?.as_ref()?;
+        snake.last_resorts(self, idx, our_team).first().copied()
+    }
 
     pub fn heuristic(&self, fast: bool) -> i32 {
-        let fast_heuristic: i32;
-        if let Some(v) = self.stored_fast_heuristic.get() {
-            fast_heuristic = v;
-        }
-        else {
-            fast_heuristic = self.fast_heuristic();
-        }
+        let fast_heuristic = match self.stored_fast_heuristic.get() {
+            Some(v) => v,
+            None => self.fast_heuristic(),
+        };
         if fast || fast_heuristic == i32::MIN || fast_heuristic == i32::MAX {
             return fast_heuristic;
         }
-        
-        let flood_fill_heuristic: i32;
-        if let Some(v) = self.stored_flood_fill_heuristic.get() {
-            flood_fill_heuristic = v;
-        } else {
-            flood_fill_heuristic = self.flood_fill().len() as i32;
+
+        // Once the board is split, how long each side survives in its region decides
+        if !self.is_solo() && endgame::isolated(self) {
+            if let Some(v) = endgame::evaluate(self) {
+                return v;
+            }
         }
-        
-        return fast_heuristic + flood_fill_heuristic;
+
+        let flood_fill_heuristic = match self.stored_flood_fill_heuristic.get() {
+            Some(v) => v,
+            None => self.flood_fill().len() as i32,
+        };
+
+        fast_heuristic + flood_fill_heuristic + food::ownership(self)
     }
 
     fn fast_heuristic(&self) -> i32 {
-        if self.snakes.len() == 0 {
+        if self.snakes.is_empty() {
             self.stored_fast_heuristic.set(Some(0));
             return 0;
         }
+        let weights = &config().weights;
         let mut health_value: i32 = 0;
         let mut length_value: i32 = 0;
         let mut death_value: i32 = 0;
         let mut dead_snake_count = 0;
         // lägg in så man är 1 längre än motståndare
-        for f_idx in self.team {
-            if f_idx >= self.snakes.len() {
-                // Index out of range, treat as None
-                dead_snake_count += 1;
-                death_value -= 1;
-            } else {
-                match &self.snakes[f_idx] {
-                    Some(snake) => {
-                        length_value += snake.body.len() as i32;
-                        if snake.health < 20 {
-                            health_value -= 20 - snake.health;
-                        }
-                    }
-                    None => {
-                        //info!("Dead snake in our team");
-                        dead_snake_count += 1;
-                        death_value -= 1;
+        for &f_idx in self.team() {
+            match self.snakes.get(f_idx).and_then(|s| s.as_ref()) {
+                Some(snake) => {
+                    length_value += snake.body.len() as i32;
+                    if snake.health < weights.low_health {
+                        health_value -= weights.low_health - snake.health;
                     }
                 }
+                None => {
+                    //info!("Dead snake in our team");
+                    dead_snake_count += 1;
+                    death_value -= 1;
+                }
             }
         }
-        if dead_snake_count == 2 {
+        if dead_snake_count == self.team().len() {
             self.stored_fast_heuristic.set(Some(i32::MIN));
-            info!("both snakes dead");
+            info!("all snakes in our team dead");
             return i32::MIN;
         }
+        // Opposing teams are scored one by one and only the strongest one counts against
+        // us, so extra snakes in a free-for-all don't drown out the rest of the evaluation
         dead_snake_count = 0;
-        for e_idx in self.opps {
-            if e_idx >= self.snakes.len() {
-                // Index out of range, treat as None
-                dead_snake_count += 1;
-                death_value += 1;
-            } else {
-                match &self.snakes[e_idx] {
+        let mut opp_count = 0;
+        let mut strongest_opp: Option<i32> = None;
+        for opp_team in self.teams.iter().skip(1) {
+            let mut team_value = 0;
+            for &e_idx in opp_team {
+                opp_count += 1;
+                match self.snakes.get(e_idx).and_then(|s| s.as_ref()) {
                     Some(snake) => {
-                        length_value -= snake.body.len() as i32;
-                        if snake.health < 20 {
-                            health_value += 20 - snake.health;
+                        team_value += snake.body.len() as i32 * weights.opponent_length;
+                        if snake.health < weights.low_health {
+                            team_value -= weights.low_health - snake.health;
                         }
                     }
                     None => {
@@ -219,65 +296,25 @@ impl SimpleBoard {
                     }
                 }
             }
+            strongest_opp = Some(strongest_opp.map_or(team_value, |v| v.max(team_value)));
         }
-        if dead_snake_count == 2 {
+        // Without opposing teams (solo) there is nobody left to beat
+        if !self.is_solo() && dead_snake_count == opp_count {
             self.stored_fast_heuristic.set(Some(i32::MAX));
             return i32::MAX;
         }
-        let v = health_value * 1 + length_value * 8 + death_value * 20;
+        let v = health_value + length_value * weights.length + death_value * weights.death - strongest_opp.unwrap_or(0);
         self.stored_fast_heuristic.set(Some(v));
         v
     }
 
-    fn flood_fill_heuristic(&self) -> i32 {
-        let flood_fill = self.flood_fill();
-        let mut sum_value = 0;
-        let mut danger_value = 0;
-        for f_idx in self.team {
-            if f_idx >= self.snakes.len() {
-                // Index out of range, treat as None
-                continue;
-            }
-            match &self.snakes[f_idx] {
-                Some(snake) => {
-                    let ff_size = flood_fill.get(&f_idx).unwrap().len() as i32;
-                    sum_value += ff_size;
-                    if ff_size < snake.body.len() as i32 {
-                        danger_value -= snake.body.len() as i32 - ff_size;
-                    }
-                }
-                None => {}
-            }
-        }
-        for e_idx in self.opps {
-            if e_idx >= self.snakes.len() {
-                // Index out of range, treat as None
-                continue;
-            }
-            match &self.snakes[e_idx] {
-                Some(snake) => {
-                    let ff_size = flood_fill.get(&e_idx).unwrap().len() as i32;
-                    sum_value -= ff_size;
-                    if ff_size < snake.body.len() as i32 {
-                        danger_value += snake.body.len() as i32 - ff_size;
-                    }
-                }
-                None => {}
-            }
-        }
-        let v = sum_value * 1 + danger_value * 4;
-        self.stored_flood_fill_heuristic.set(Some(v));
-        v
-    }
-
     fn flood_fill(&self) -> HashMap<usize, Vec<Coord>> {
         let mut mapping = HashMap::new();
         let mut queue: Vec<(usize, Coord)> = self
             .snakes
             .iter()
             .enumerate()
-            .filter(|(_, o)| o.is_some())
-            .map(|(i, s)| (i, s.as_ref().unwrap().body[0]))
+            .filter_map(|(i, s)| s.as_ref().map(|s| (i, s.body[0])))
             .collect();
         queue.sort_by_key(|&(i, _)| self.snakes[i].as_ref().unwrap().body.len());
         let mut queue = VecDeque::from(queue);
@@ -286,7 +323,7 @@ impl SimpleBoard {
         }
         let mut visited = [false; 121];
         while let Some((i, coord)) = queue.pop_front() {
-            if coord.x < 0 || coord.x > 10 || coord.y < 0 || coord.y > 10 {
+            if !(0..=10).contains(&coord.x) || !(0..=10).contains(&coord.y) {
                 continue;
             }
             let arr_idx = (coord.y * 11 + coord.x) as usize;
@@ -298,7 +335,7 @@ impl SimpleBoard {
             for (dx, dy) in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
                 let nx = coord.x + dx;
                 let ny = coord.y + dy;
-                if nx >= 0 && nx < 11 && ny >= 0 && ny < 11 {
+                if (0..11).contains(&nx) && (0..11).contains(&ny) {
                     queue.push_back((i, Coord { x: nx, y: ny }));
                 }
             }
@@ -306,127 +343,132 @@ impl SimpleBoard {
         mapping
     }
 
-    // This could be using team instead of index and then do the combined moves
-    pub fn simulate_move(&self, our_team: bool) -> Vec<([SnakeMove; 2], Self)> {
+    // Every combination of safe moves for the living snakes on one side. All opposing
+    // teams move together so the search stays a two player game against us.
+    pub fn simulate_move(&self, our_team: bool) -> Vec<(JointMove, Self)> {
         // reset stored heuristics since snakes have moved
         self.stored_fast_heuristic.set(None);
         self.stored_flood_fill_heuristic.set(None);
 
-        let idx = if our_team { self.team } else { self.opps };
-        let mut moves = Vec::new();
-        let mut alive = [false; 4];
-        for i in idx {
-            if let Some(snake) = &self.snakes[i] {
-                alive[i] = true;
-                let mut m = snake.get_safe_moves(self, our_team);
-                if m.len() == 0 {
-                    m.push(Movement::Down);
+        let idx: Vec<usize> = self
+            .side(our_team)
+            .into_iter()
+            .filter(|&i| matches!(self.snakes.get(i), Some(Some(_))))
+            .collect();
+        let snake = |i: usize| self.snakes[i].as_ref().unwrap();
+        // A snake without a safe move plays its last resorts, so the search still sees
+        // which of them it might survive
+        let options: Vec<Vec<Movement>> = idx
+            .iter()
+            .map(|&i| {
+                let safe = snake(i).get_safe_moves(self, our_team);
+                if safe.is_empty() {
+                    snake(i).last_resorts(self, i, our_team)
+                } else {
+                    safe
                 }
-                moves.push(
-                    m.iter()
-                        .map(|&m| SnakeMove { id: i, mv: m })
-                        .collect::<Vec<SnakeMove>>(),
-                );
-            } else {
-                moves.push(vec![SnakeMove {
-                    id: i,
-                    mv: Movement::Down,
-                }]);
-            }
+            })
+            .collect();
+        let simulations = self.simulate_joint_moves(&idx, &options, our_team);
+        if !simulations.is_empty() {
+            return simulations;
         }
 
+        // Every joint move puts two team mates on the same cell, so each snake also gets
+        // its last resorts and one of them can make way for the other
+        let options: Vec<Vec<Movement>> = idx
+            .iter()
+            .zip(options)
+            .map(|(&i, mut moves)| {
+                for m in snake(i).last_resorts(self, i, our_team) {
+                    if !moves.contains(&m) {
+                        moves.push(m);
+                    }
+                }
+                moves
+            })
+            .collect();
+        let simulations = self.simulate_joint_moves(&idx, &options, our_team);
+        if !simulations.is_empty() {
+            return simulations;
+        }
+        let moves = idx
+            .iter()
+            .zip(options)
+            .map(|(&id, moves)| SnakeMove { id, mv: moves[0] })
+            .collect();
+        vec![(moves, self.clone())]
+    }
+
+    // Boards after every combination of the given moves for the snakes at idx
+    fn simulate_joint_moves(&self, idx: &[usize], options: &[Vec<Movement>], our_team: bool) -> Vec<(JointMove, Self)> {
+        let moves: Vec<Vec<SnakeMove>> = idx
+            .iter()
+            .zip(options)
+            .map(|(&i, m)| m.iter().map(|&mv| SnakeMove { id: i, mv }).collect())
+            .collect();
+
         let mut simulations = Vec::new();
-        let team_moves: Vec<[SnakeMove; 2]> = cartesian_move(&moves[0], &moves[1]).collect();
-        for m in team_moves {
-            let next_pos = [
-                if alive[idx[0]] {
-                    self.snakes[idx[0]]
-                        .as_ref()
-                        .unwrap()
-                        .next_position(m.iter().find(|mv| mv.id == idx[0]).unwrap().mv)
-                } else {
-                    Coord { x: -2, y: -1 }
-                },
-                if alive[idx[1]] {
-                    self.snakes[idx[1]]
-                        .as_ref()
-                        .unwrap()
-                        .next_position(m.iter().find(|mv| mv.id == idx[1]).unwrap().mv)
-                } else {
-                    Coord { x: -1, y: -2 }
-                },
-            ];
-            if next_pos[0] == next_pos[1] {
+        for m in cartesian_moves(&moves) {
+            let next_pos: Vec<Coord> = m
+                .iter()
+                .map(|mv| self.snakes[mv.id].as_ref().unwrap().next_position(mv.mv))
+                .collect();
+            // Snakes in the same team never move onto the same cell
+            let same_team_clash = (0..m.len()).any(|a| {
+                (a + 1..m.len()).any(|b| {
+                    next_pos[a] == next_pos[b] && self.team_of(m[a].id) == self.team_of(m[b].id)
+                })
+            });
+            if same_team_clash {
                 continue;
             }
 
             let mut next_board = self.clone();
-            if alive[idx[0]] {
-                next_board.snakes[idx[0]]
-                    .as_mut()
-                    .unwrap()
-                    .body
-                    .push_front(next_pos[0]);
-
-                if !next_board.food.contains(&next_pos[0]) {
-                    next_board.snakes[idx[0]].as_mut().unwrap().body.pop_back();
+            for (mv, &pos) in m.iter().zip(next_pos.iter()) {
+                let snake = next_board.snakes[mv.id].as_mut().unwrap();
+                snake.body.push_front(pos);
+                snake.body.pop_back();
+                snake.health -= 1;
+                // Eating heals fully and grows by repeating the tail, like the official rules
+                if self.food.contains(&pos) {
+                    snake.health = 100;
+                    let tail = *snake.body.back().unwrap();
+                    snake.body.push_back(tail);
                 }
-                next_board.snakes[idx[0]].as_mut().unwrap().health -= 1;
             }
-            if alive[idx[1]] {
-                next_board.snakes[idx[1]]
-                    .as_mut()
-                    .unwrap()
-                    .body
-                    .push_front(next_pos[1]);
-
-                if !next_board.food.contains(&next_pos[1]) {
-                    next_board.snakes[idx[1]].as_mut().unwrap().body.pop_back();
-                }
-                next_board.snakes[idx[1]].as_mut().unwrap().health -= 1;
-            }
-
-            next_board
-                .food
-                .retain(|f| f != &next_pos[0] && f != &next_pos[1]);
 
             //info!("Simulating move: {:?} -> \n{}", m, next_board);
 
-            if !our_team { next_board.kill_snakes(); }
+            // Food is only removed once both sides have moved, so an opponent reaching
+            // the cell our snake just ate from in the same turn eats as well
+            if !our_team {
+                let heads: Vec<Coord> = next_board.snakes.iter().flatten().map(|s| s.head()).collect();
+                next_board.food.retain(|f| !heads.contains(f));
+                next_board.kill_snakes();
+            }
 
             //info!("Killed snakes: \n{}", next_board);
 
             simulations.push((m, next_board));
         }
-
-        // Det här behöver ersättas med lösning för att låta en leva om de "måste" huvudkrocka
-        // Det eller att det inte finns några safe moves är enda sätten simulations kan ge 0 moves
-        if simulations.len() == 0 {
-            return vec![(
-                [
-                    SnakeMove {
-                        id: idx[0],
-                        mv: Movement::Down,
-                    },
-                    SnakeMove {
-                        id: idx[1],
-                        mv: Movement::Down,
-                    },
-                ],
-                self.clone(),
-            )];
-        }
         simulations
     }
 
     fn kill_snakes(&mut self) {
+        // Starved snakes and those off the board are gone before collisions are checked,
+        // like the rules, so nobody runs into them
+        for o_snake in self.snakes.iter_mut() {
+            if let Some(snake) = o_snake {
+                if snake.health == 0 || simple_out_of_bounds(&snake.body[0], &Movement::None) {
+                    *o_snake = None;
+                }
+            }
+        }
         let mut kill_idxs = Vec::new();
         for (i, o_snake) in self.snakes.iter().enumerate() {
             if let Some(snake) = o_snake {
-                if snake.health == 0
-                    || simple_out_of_bounds(&snake.body[0], &Movement::None)
-                    || snake.collision_with_snakes(&self)
-                {
+                if snake.collision_with_snakes(self) {
                     kill_idxs.push(i);
                     continue;
                 }
@@ -439,53 +481,41 @@ impl SimpleBoard {
 }
 impl std::fmt::Display for SimpleBoard {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        /* build board representation string */
-        let mut board: String = "\n|:---------:|".to_owned();
-        for y in (0..11).rev() {
-            board += "\n|";
-            for x in 0..11 {
-                let coord = Coord {
-                    x: x as i32,
-                    y: y as i32,
-                };
-                let piece: String = if self.food.contains(&coord) {
-                    "f".to_string()
-                } else if let Some(snake) = self
-                    .snakes
-                    .iter()
-                    .filter_map(|s| s.as_ref())
-                    .find(|s| s.body.contains(&coord))
-                {
-                    if snake.body[0] == coord {
-                        "h".to_string()
-                    } else {
-                        "s".to_string()
-                    }
-                } else {
-                    " ".to_string()
-                };
-                board += &piece;
-            }
-            board += "|";
-        }
-        board += "\n|:---------:|";
-
-        write!(f, "{}", board)
+        write!(f, "\n{}", render::terminal(&render::Scene::from(self), false))
     }
 }
 
 // Galenskap hehe
-fn cartesian_move<'a>(
-    v1: &'a [SnakeMove],
-    v2: &'a [SnakeMove],
-) -> impl Iterator<Item = [SnakeMove; 2]> + 'a {
-    let ret = v1
-        .iter()
-        .flat_map(move |&m1| v2.iter().map(move |&m2| [m1, m2]));
-    //info!("Cartesian product from {:?} and {:?} = {:?}", v1, v2, ret.clone().collect::<Vec<_>>());
+fn cartesian_moves(moves: &[Vec<SnakeMove>]) -> Vec<JointMove> {
+    let ret = moves.iter().fold(vec![Vec::new()], |acc: Vec<JointMove>, options| {
+        acc.iter()
+            .flat_map(|prefix| {
+                options.iter().map(move |&m| {
+                    let mut joint = prefix.clone();
+                    joint.push(m);
+                    joint
+                })
+            })
+            .collect()
+    });
+    //info!("Cartesian product from {:?} = {:?}", moves, ret);
     ret
 }
 
+// What a move without a safe landing can still lead to, best first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LastResort {
+    // Onto an opponent's tail that moves on unless the opponent eats
+    TailMayMove,
+    // Into a head of equal length, both snakes die
+    HeadToHead,
+    // Off the board, which removes the snake before it can take anyone with it
+    OffTheBoard,
+    Loss,
+    // A loss on a cell a team mate could need
+    LossInTheWay,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SimpleSnake {
     health: i32,
@@ -493,6 +523,13 @@ pub struct SimpleSnake {
 }
 
 impl SimpleSnake {
+    pub fn new(body: Vec<Coord>, health: i32) -> Self {
+        SimpleSnake {
+            health,
+            body: VecDeque::from(body),
+        }
+    }
+
     pub fn from(snake: &Battlesnake) -> Self {
         SimpleSnake {
             health: snake.health,
@@ -500,6 +537,14 @@ impl SimpleSnake {
         }
     }
 
+    pub fn body(&self) -> &VecDeque<Coord> {
+        &self.body
+    }
+
+    pub fn health(&self) -> i32 {
+        self.health
+    }
+
     fn get_safe_moves(&self, simple_board: &SimpleBoard, our_team: bool) -> Vec<Movement> {
         let mut m_v = Movement::all();
         let head = &self.body[0];
@@ -522,16 +567,77 @@ impl SimpleSnake {
                 m_v.remove(idx);
             }
         }
-        
+
         m_v.retain(|&m| {
-            !simple_out_of_bounds(&head, &m)
-                && (if our_team { !self.team_collision(simple_board, m) } else { !self.opps_collision(simple_board, m) })
+            !simple_out_of_bounds(head, &m)
+                && (if our_team {
+                    !self.team_collision(simple_board, m)
+                } else {
+                    !self.opps_collision(simple_board, m)
+                })
         });
 
         m_v
     }
 
-    fn next_position(&self, movement: Movement) -> Coord {
+    // Moves for a snake without a safe move, the ones it might live through first, then
+    // the head-to-heads that take an opponent with it, then a single move that surely
+    // loses so there is always one left, off the board or away from its team mates if
+    // it can. Our team moves first in the search, so only our snakes find opponent tails
+    // that may still move away, and only opponents can run into a head of ours.
+    fn last_resorts(&self, simple_board: &SimpleBoard, idx: usize, our_team: bool) -> Vec<Movement> {
+        let neck = self.body.get(1).copied();
+        let mut ranked: Vec<(LastResort, Movement)> = Movement::all()
+            .into_iter()
+            .filter(|&m| Some(self.next_position(m)) != neck)
+            .map(|m| (self.last_resort(simple_board, idx, our_team, m), m))
+            .collect();
+        ranked.sort();
+        if let Some(first_loss) = ranked.iter().position(|(r, _)| *r >= LastResort::OffTheBoard) {
+            ranked.truncate(first_loss + 1);
+        }
+        ranked.into_iter().map(|(_, m)| m).collect()
+    }
+
+    fn last_resort(&self, simple_board: &SimpleBoard, idx: usize, our_team: bool, movement: Movement) -> LastResort {
+        let next_pos = self.next_position(movement);
+        if simple_out_of_bounds(&self.body[0], &movement) {
+            return LastResort::OffTheBoard;
+        }
+        let team = simple_board.team_of(idx);
+        let mut in_the_way = false;
+        for (other, snake) in simple_board.snakes.iter().enumerate() {
+            let Some(snake) = snake else {
+                continue;
+            };
+            let ours = simple_board.team_of(other) == Some(0);
+            let head = snake.head();
+            let next_to = (head.x - next_pos.x).abs() + (head.y - next_pos.y).abs() == 1;
+            if other != idx && simple_board.team_of(other) == team && next_to {
+                in_the_way = true;
+            }
+            let len = snake.body.len();
+            // A tail stays where it is for a turn after eating
+            let stacked = len > 1 && snake.body[len - 1] == snake.body[len - 2];
+            if our_team && !ours && snake.body.back() == Some(&next_pos) && !stacked {
+                return LastResort::TailMayMove;
+            }
+            if !our_team && ours && head == next_pos && len == self.body.len() {
+                return LastResort::HeadToHead;
+            }
+        }
+        if in_the_way {
+            LastResort::LossInTheWay
+        } else {
+            LastResort::Loss
+        }
+    }
+
+    pub fn head(&self) -> Coord {
+        self.body[0]
+    }
+
+    pub fn next_position(&self, movement: Movement) -> Coord {
         let head = &self.body[0];
         match movement {
             Movement::Up => Coord {
@@ -550,23 +656,14 @@ impl SimpleSnake {
                 x: head.x + 1,
                 y: head.y,
             },
-            Movement::None => head.clone(),
+            Movement::None => *head,
         }
     }
 
-    fn collision_with_body(&self, movement: Movement) -> bool {
+    fn team_collision(&self, simple_board: &SimpleBoard, movement: Movement) -> bool {
         let next_pos = self.next_position(movement);
-        self.body.iter().any(|b| b == &next_pos)
-    }
-
-    fn team_collision(
-        &self,
-        simple_board: &SimpleBoard,
-        movement: Movement,
-    ) -> bool {
-        let next_pos = self.next_position(movement);
-        for idx in simple_board.team {
-            if let Some(snake) = &simple_board.snakes[idx] {
+        for &idx in simple_board.team() {
+            if let Some(Some(snake)) = simple_board.snakes.get(idx) {
                 if let Some(&pos) = snake.body.back() {
                     if pos == next_pos {
                         return false;
@@ -574,15 +671,15 @@ impl SimpleSnake {
                 }
             }
         }
-        for idx in simple_board.opps {
-            if let Some(snake) = &simple_board.snakes[idx] {
+        for idx in simple_board.opps() {
+            if let Some(Some(snake)) = simple_board.snakes.get(idx) {
                 if let Some(&pos) = snake.body.back() {
                     if pos == next_pos {
                         let head = snake.body.front().unwrap();
                         for (dx, dy) in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
                             let nx = head.x + dx;
                             let ny = head.y + dy;
-                            if nx >= 0 && nx < 11 && ny >= 0 && ny < 11 {
+                            if (0..11).contains(&nx) && (0..11).contains(&ny) {
                                 let new_coord = Coord { x: nx, y: ny };
                                 if simple_board.food.contains(&new_coord) {
                                     return true;
@@ -595,27 +692,17 @@ impl SimpleSnake {
             }
         }
 
-        for s in simple_board.snakes.iter().filter(|s| s.is_some()) {
-            let collision = s
-                .as_ref()
-                .map_or(false, |snake| snake.body.contains(&next_pos));
-            if collision {
-                return true;
-            }
-        }
-        return false;
+        simple_board
+            .snakes
+            .iter()
+            .flatten()
+            .any(|snake| snake.body.contains(&next_pos))
     }
-    
-    fn opps_collision(
-        &self,
-        simple_board: &SimpleBoard,
-        movement: Movement,
-    ) -> bool {
+
+    fn opps_collision(&self, simple_board: &SimpleBoard, movement: Movement) -> bool {
         let next_pos = self.next_position(movement);
-        let mut any_collision = false;
-        let mut dead = false;
-        for idx in simple_board.opps {
-            if let Some(snake) = &simple_board.snakes[idx] {
+        for idx in simple_board.opps() {
+            if let Some(Some(snake)) = simple_board.snakes.get(idx) {
                 if let Some(&pos) = snake.body.back() {
                     if pos == next_pos {
                         return false;
@@ -623,53 +710,37 @@ impl SimpleSnake {
                 }
             }
         }
-        for s in simple_board.snakes.iter().filter(|s| s.is_some()) {
-            let collision = s
-                .as_ref()
-                .map_or(false, |snake| snake.body.contains(&next_pos));
-            if collision{
+        for snake in simple_board.snakes.iter().flatten() {
+            if snake.body.contains(&next_pos) {
                 // Only check length if collision is with the head, otherwise always dead
-                if s.as_ref().unwrap().body.front().unwrap() == &next_pos {
-                    if s.as_ref().unwrap().body.len() >= self.body.len() {
-                        return true;
-                    }
-                    return false;
+                if snake.body.front().unwrap() == &next_pos {
+                    return snake.body.len() >= self.body.len();
                 } else {
                     return true;
                 }
             }
         }
-        return false;
+        false
     }
 
-    fn collision_with_snakes (
-        &self,
-        simple_board: &SimpleBoard,
-    ) -> bool {
+    fn collision_with_snakes(&self, simple_board: &SimpleBoard) -> bool {
         let head = self.body.front().unwrap();
 
-        for s in simple_board.snakes.iter().filter(|s| s.is_some()) {
-            let collision = s
-                .as_ref()
-                .map_or(false, |snake| snake.body.contains(&head));
-            if collision {
+        for snek in simple_board.snakes.iter().flatten() {
+            if snek.body.contains(head) {
                 // Only check length if collision is with the head, otherwise always dead
-                let snek = s.as_ref().unwrap(); 
                 if snek.body.front().unwrap() == head {
                     // If the head is also present elsewhere in the body (shouldn't happen in normal play, but check)
                     if snek == self && !snek.body.iter().skip(1).any(|pos| pos == head) {
                         continue;
                     }
-                    if snek.body.len() >= self.body.len() {
-                        return true;
-                    }
-                    return false;
+                    return snek.body.len() >= self.body.len();
                 } else {
                     return true;
                 }
             }
         }
-        return false;
+        false
     }
 }
 
@@ -685,9 +756,14 @@ fn simple_out_of_bounds(coord: &Coord, movement: &Movement) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::VecDeque;
-    use crate::{Coord, Movement};
-    use super::{SimpleBoard, SimpleSnake};
+    use std::collections::{HashMap, VecDeque};
+    use crate::rules;
+    use crate::{Battlesnake, Board, Coord, GameInfo, Movement};
+    use super::{simple_out_of_bounds, JointMove, SimpleBoard, SimpleSnake};
+    use crate::notation::Position;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
 
     fn create_snake_at(body: Vec<Coord>, health: i32) -> SimpleSnake {
         let mut body_deque = VecDeque::new();
@@ -704,8 +780,7 @@ mod tests {
         SimpleBoard {
             food: vec![],
             snakes: vec![Some(snake), None, None, None],
-            team: [0, 1],
-            opps: [2, 3],
+            teams: vec![vec![0, 1], vec![2, 3]],
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         }
@@ -764,10 +839,14 @@ mod tests {
         assert_eq!(moves, expected);
     }
 
+    fn position(text: &str) -> SimpleBoard {
+        text.parse::<Position>().unwrap().simple_board()
+    }
+
     #[test]
     fn test_enemy_collision_blocked() {
         // Snake near enemy that blocks the right
-        let mut snake = create_snake_at(
+        let snake = create_snake_at(
             vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }],
             100,
         );
@@ -779,8 +858,7 @@ mod tests {
         let board = SimpleBoard {
             food: vec![],
             snakes: vec![Some(snake.clone()), Some(enemy_snake), None, None],
-            team: [0, 2],
-            opps: [1, 3],
+            teams: vec![vec![0, 2], vec![1, 3]],
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -792,6 +870,17 @@ mod tests {
         assert_eq!(moves, expected);
     }
 
+    #[test]
+    fn test_enemy_collision_blocked_in_notation() {
+        // Snake near enemy that blocks the right
+        let board = position("
+            . . . . . . .
+            . . . . . A B
+            . . . . . a b
+        ");
+        assert_eq!(board.safe_moves(0), vec![Movement::Up, Movement::Left]);
+    }
+
     #[test]
     fn test_team_tail() {
         let snake = create_snake_at(
@@ -816,8 +905,7 @@ mod tests {
         let board = SimpleBoard {
             food: vec![],
             snakes: vec![Some(snake.clone()), Some(teammate), None, None],
-            team: [0, 1],
-            opps: [2, 3],
+            teams: vec![vec![0, 1], vec![2, 3]],
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -861,8 +949,7 @@ mod tests {
         let board = SimpleBoard {
             food: vec![Coord { x: 0, y: 5 }],
             snakes: vec![Some(snake.clone()), Some(enemy1), Some(enemy2), None],
-            team: [0, 3],
-            opps: [1, 2],
+            teams: vec![vec![0, 3], vec![1, 2]],
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -897,8 +984,7 @@ mod tests {
         let board = SimpleBoard {
             food: vec![],
             snakes: vec![Some(snake.clone()), Some(teammate), None, None],
-            team: [2, 3],
-            opps: [0, 1],
+            teams: vec![vec![2, 3], vec![0, 1]],
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -942,8 +1028,7 @@ mod tests {
         let board = SimpleBoard {
             food: vec![Coord { x: 0, y: 5 }],
             snakes: vec![Some(snake.clone()), Some(enemy1), Some(enemy2), None],
-            team: [1, 2],
-            opps: [0, 3],
+            teams: vec![vec![1, 2], vec![0, 3]],
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -977,8 +1062,7 @@ mod tests {
         let board = SimpleBoard {
             food: vec![],
             snakes: vec![Some(snake.clone()), Some(enemy), None, None],
-            team: [0, 3],
-            opps: [1, 2],
+            teams: vec![vec![0, 3], vec![1, 2]],
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -989,6 +1073,17 @@ mod tests {
         assert_eq!(moves, vec![Movement::Up, Movement::Down]);
     }
 
+    #[test]
+    fn test_head_in_notation() {
+        // Right is the head of a shorter enemy, our team still doesn't move there
+        let board = position("
+            . . . . . . . . . . .
+            . . . . a a a A B b b
+            . . . . . . . . . . .
+        ");
+        assert_eq!(board.safe_moves(0), vec![Movement::Up, Movement::Down]);
+    }
+
     #[test]
     fn test_head_opps_longer() {
         let enemy = create_snake_at(
@@ -1012,8 +1107,7 @@ mod tests {
         let board = SimpleBoard {
             food: vec![],
             snakes: vec![Some(snake.clone()), Some(enemy), None, None],
-            team: [1, 3],
-            opps: [0, 2],
+            teams: vec![vec![1, 3], vec![0, 2]],
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -1047,8 +1141,7 @@ mod tests {
         let board = SimpleBoard {
             food: vec![],
             snakes: vec![Some(snake), Some(enemy.clone()), None, None],
-            team: [0, 3],
-            opps: [1, 2],
+            teams: vec![vec![0, 3], vec![1, 2]],
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -1074,8 +1167,7 @@ mod tests {
                 Some(snake3),
                 Some(snake4),
             ],
-            team: [0, 1],
-            opps: [2, 3],
+            teams: vec![vec![0, 1], vec![2, 3]],
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -1142,4 +1234,460 @@ mod tests {
         // Assert that all snakes are None (dead)
         assert!(sim2[0].1.snakes.iter().all(|s| s.is_none()));
     }
+
+    #[test]
+    fn test_starved_and_off_board_snakes_go_first() {
+        // Our head ran into the body of an opponent that starved on the same turn
+        let snake = create_snake_at(vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }], 50);
+        let starved = create_snake_at(
+            vec![Coord { x: 7, y: 5 }, Coord { x: 6, y: 5 }, Coord { x: 5, y: 5 }, Coord { x: 4, y: 5 }],
+            0,
+        );
+        // And a team mate into the body of one that left the board
+        let mate = create_snake_at(vec![Coord { x: 0, y: 8 }, Coord { x: 1, y: 8 }], 50);
+        let gone = create_snake_at(
+            vec![Coord { x: -1, y: 9 }, Coord { x: 0, y: 9 }, Coord { x: 0, y: 8 }, Coord { x: 0, y: 7 }],
+            50,
+        );
+        let mut board = SimpleBoard::new(
+            vec![],
+            vec![Some(snake), Some(mate), Some(starved), Some(gone)],
+            vec![vec![0, 1], vec![2, 3]],
+        );
+
+        board.kill_snakes();
+        assert!(board.snakes[0].is_some());
+        assert!(board.snakes[1].is_some());
+        assert!(board.snakes[2].is_none());
+        assert!(board.snakes[3].is_none());
+    }
+
+    #[test]
+    fn test_eating_heals_and_grows() {
+        let snake = create_snake_at(
+            vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }, Coord { x: 5, y: 3 }],
+            30,
+        );
+        let mut board = basic_board_with_snake(snake);
+        board.food = vec![Coord { x: 5, y: 6 }];
+
+        let (_, half) = board.simulate_move(true).into_iter().find(|(m, _)| m[0].mv == Movement::Up).unwrap();
+        let (_, next) = half.simulate_move(false).remove(0);
+        let snake = next.snakes[0].as_ref().unwrap();
+        assert_eq!(snake.health, 100);
+        // The tail is repeated and stays where it is for the next move
+        assert_eq!(
+            snake.body,
+            VecDeque::from(vec![
+                Coord { x: 5, y: 6 },
+                Coord { x: 5, y: 5 },
+                Coord { x: 5, y: 4 },
+                Coord { x: 5, y: 4 },
+            ])
+        );
+        let (_, after) = next.simulate_move(true).into_iter().find(|(m, _)| m[0].mv == Movement::Up).unwrap();
+        let snake = after.snakes[0].as_ref().unwrap();
+        assert_eq!(snake.health, 99);
+        assert_eq!(snake.body.len(), 4);
+        assert_eq!(snake.body.back(), Some(&Coord { x: 5, y: 4 }));
+    }
+
+    #[test]
+    fn test_food_eaten_by_both_sides() {
+        let snake = create_snake_at(
+            vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }, Coord { x: 5, y: 3 }],
+            100,
+        );
+        let enemy = create_snake_at(
+            vec![
+                Coord { x: 5, y: 7 },
+                Coord { x: 5, y: 8 },
+                Coord { x: 5, y: 9 },
+                Coord { x: 5, y: 10 },
+                Coord { x: 6, y: 10 },
+            ],
+            50,
+        );
+        let board = SimpleBoard::new(
+            vec![Coord { x: 5, y: 6 }],
+            vec![Some(snake), None, Some(enemy), None],
+            vec![vec![0, 1], vec![2, 3]],
+        );
+
+        let (_, half) = board.simulate_move(true).into_iter().find(|(m, _)| m[0].mv == Movement::Up).unwrap();
+        // The food stays until the opponents have moved as well
+        assert_eq!(half.food, vec![Coord { x: 5, y: 6 }]);
+        let (_, next) = half.simulate_move(false).into_iter().find(|(m, _)| m[0].mv == Movement::Down).unwrap();
+        assert!(next.food.is_empty());
+        assert!(next.snakes[0].is_none());
+        // The longer opponent ate the same food and won the head-to-head
+        let enemy = next.snakes[2].as_ref().unwrap();
+        assert_eq!(enemy.body.len(), 6);
+        assert_eq!(enemy.health, 100);
+    }
+
+    #[test]
+    fn test_three_snake_team() {
+        let snakes = vec![
+            Some(create_snake_at(vec![Coord { x: 5, y: 5 }], 100)),
+            Some(create_snake_at(vec![Coord { x: 7, y: 5 }], 100)),
+            Some(create_snake_at(vec![Coord { x: 1, y: 9 }], 100)),
+            Some(create_snake_at(vec![Coord { x: 9, y: 1 }], 100)),
+        ];
+        let board = SimpleBoard::new(vec![], snakes, vec![vec![0, 1, 2], vec![3]]);
+
+        let simulations = board.simulate_move(true);
+        // 4 * 4 * 4 combinations minus the 4 where 0 and 1 both enter (6, 5)
+        assert_eq!(simulations.len(), 60);
+        assert!(simulations.iter().all(|(m, _)| m.len() == 3));
+    }
+
+    fn moves_of(simulations: &[(JointMove, SimpleBoard)], id: usize) -> Vec<Movement> {
+        let mut moves: Vec<Movement> = simulations.iter().flat_map(|(m, _)| m.iter().filter(|s| s.id == id).map(|s| s.mv)).collect();
+        moves.dedup();
+        moves
+    }
+
+    #[test]
+    fn test_last_resort_tail_may_move() {
+        // With food next to B's head its tail doesn't count as safe, but a tail only stays
+        // put the turn after eating, so our snake gets out whatever B does
+        let board = position("
+            * B . .
+            b b . .
+            A a a .
+        ");
+        assert!(board.safe_moves(0).is_empty());
+        let simulations = board.simulate_move(true);
+        assert_eq!(moves_of(&simulations, 0), vec![Movement::Up, Movement::Down]);
+
+        let replies = simulations[0].1.simulate_move(false);
+        assert!(replies.iter().all(|(_, next)| next.snakes[0].is_some()));
+        // Off the board is a loss
+        let replies = simulations[1].1.simulate_move(false);
+        assert!(replies.iter().all(|(_, next)| next.snakes[0].is_none()));
+    }
+
+    #[test]
+    fn test_last_resort_head_to_head() {
+        // Cornered by our head of the same length, B takes us with it
+        let board = position("
+            b . . .
+            b . . .
+            B A a a
+        ");
+        let simulations = board.simulate_move(false);
+        assert_eq!(moves_of(&simulations, 1), vec![Movement::Right, Movement::Down]);
+        assert!(simulations[0].1.snakes.iter().all(|s| s.is_none()));
+        assert!(simulations[1].1.snakes[0].is_some());
+    }
+
+    #[test]
+    fn test_last_resort_makes_way_for_team_mate() {
+        // Both of our snakes only have (1, 0) left, one of them has to go off the board
+        let board = position("
+            a . b c
+            a . b c
+            A . B C
+            B: team=0
+        ");
+        let simulations = board.simulate_move(true);
+        let joint: Vec<Vec<Movement>> = simulations.iter().map(|(m, _)| m.iter().map(|s| s.mv).collect()).collect();
+        assert_eq!(
+            joint,
+            vec![
+                vec![Movement::Right, Movement::Down],
+                vec![Movement::Down, Movement::Left],
+                vec![Movement::Down, Movement::Down],
+            ]
+        );
+        // After the opponent moves only the snake that went off the board is gone
+        let (_, next) = &simulations[0].1.simulate_move(false)[0];
+        assert!(next.snakes[0].is_some() && next.snakes[1].is_none());
+    }
+
+    #[test]
+    fn test_free_for_all_opps_move_together() {
+        let snakes = vec![
+            Some(create_snake_at(vec![Coord { x: 1, y: 1 }], 100)),
+            Some(create_snake_at(vec![Coord { x: 9, y: 1 }], 100)),
+            Some(create_snake_at(vec![Coord { x: 9, y: 9 }], 100)),
+            Some(create_snake_at(vec![Coord { x: 1, y: 9 }], 100)),
+        ];
+        let board = SimpleBoard::new(vec![], snakes, vec![vec![0], vec![1], vec![2], vec![3]]);
+
+        assert_eq!(board.simulate_move(true).len(), 4);
+        let simulations = board.simulate_move(false);
+        assert_eq!(simulations.len(), 64);
+        for (m, _) in &simulations {
+            let mut ids: Vec<usize> = m.iter().map(|mv| mv.id).collect();
+            ids.sort();
+            assert_eq!(ids, vec![1, 2, 3]);
+        }
+    }
+    fn api_snake(id: &str, squad: &str, body: Vec<Coord>) -> Battlesnake {
+        Battlesnake {
+            id: id.to_string(),
+            name: id.to_string(),
+            health: 100,
+            head: body[0],
+            length: body.len() as i32,
+            body,
+            latency: "0".to_string(),
+            shout: None,
+            squad: squad.to_string(),
+        }
+    }
+
+    fn game_info_for(agent_ids: &[&str]) -> GameInfo {
+        GameInfo::new(
+            "game".to_string(),
+            475,
+            agent_ids.iter().map(|id| id.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn test_from_groups_squads() {
+        let board = Board {
+            height: 11,
+            width: 11,
+            food: vec![],
+            snakes: vec![
+                api_snake("a", "red", vec![Coord { x: 1, y: 1 }]),
+                api_snake("b", "blue", vec![Coord { x: 9, y: 1 }]),
+                api_snake("c", "red", vec![Coord { x: 9, y: 9 }]),
+                api_snake("d", "blue", vec![Coord { x: 1, y: 9 }]),
+            ],
+            hazards: vec![],
+        };
+        // Squad mate "c" runs on another server but is still searched as our team
+        let simple_board = SimpleBoard::from(&board, &game_info_for(&["a"]));
+        assert_eq!(simple_board.teams, vec![vec![0, 2], vec![1, 3]]);
+    }
+
+    #[test]
+    fn test_from_free_for_all() {
+        let board = Board {
+            height: 11,
+            width: 11,
+            food: vec![],
+            snakes: vec![
+                api_snake("a", "", vec![Coord { x: 1, y: 1 }]),
+                api_snake("b", "", vec![Coord { x: 9, y: 1 }]),
+                api_snake("c", "", vec![Coord { x: 9, y: 9 }]),
+            ],
+            hazards: vec![],
+        };
+        let simple_board = SimpleBoard::from(&board, &game_info_for(&["b"]));
+        assert_eq!(simple_board.teams, vec![vec![1], vec![0], vec![2]]);
+        assert!(!simple_board.is_solo());
+    }
+
+    #[test]
+    fn test_solo_has_no_phantom_teammate() {
+        let board = Board {
+            height: 11,
+            width: 11,
+            food: vec![],
+            snakes: vec![api_snake(
+                "a",
+                "",
+                vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }, Coord { x: 5, y: 3 }],
+            )],
+            hazards: vec![],
+        };
+        let simple_board = SimpleBoard::from(&board, &game_info_for(&["a"]));
+        assert_eq!(simple_board.snakes.len(), 1);
+        assert!(simple_board.is_solo());
+        // No dead teammate penalty and no win for lack of opponents
+        assert_eq!(simple_board.heuristic(true), 3 * 8);
+
+        let simulations = simple_board.simulate_move(true);
+        assert_eq!(simulations.len(), 3);
+        let (moves, next_board) = &simulations[0];
+        assert_eq!(moves.len(), 1);
+        let replies = next_board.simulate_move(false);
+        assert_eq!(replies.len(), 1);
+        assert!(replies[0].0.is_empty());
+    }
+
+    // Random legal 2v2 positions: bodies are random walks that never cross, a few snakes
+    // have just eaten (stacked tail) or just spawned (whole body on one cell), and some
+    // snakes are already dead. Seeded so a failure can be reproduced from its seed.
+    fn random_board(rng: &mut StdRng) -> SimpleBoard {
+        let mut occupied: Vec<Coord> = Vec::new();
+        let mut snakes = Vec::new();
+        for _ in 0..4 {
+            if rng.gen_bool(0.15) {
+                snakes.push(None);
+                continue;
+            }
+            let free: Vec<Coord> = all_cells().filter(|c| !occupied.contains(c)).collect();
+            let head = *free.choose(rng).unwrap();
+            let mut body = vec![head];
+            if rng.gen_bool(0.1) {
+                body.extend([head, head]);
+            } else {
+                let len = rng.gen_range(1..=12);
+                while body.len() < len {
+                    let last = *body.last().unwrap();
+                    let next: Vec<Coord> = neighbours(last)
+                        .filter(|c| !occupied.contains(c) && !body.contains(c))
+                        .collect();
+                    match next.choose(rng) {
+                        Some(&c) => body.push(c),
+                        None => break,
+                    }
+                }
+                if body.len() > 1 && rng.gen_bool(0.2) {
+                    body.push(*body.last().unwrap());
+                }
+            }
+            occupied.extend(body.iter().copied());
+            snakes.push(Some(create_snake_at(body, rng.gen_range(1..=100))));
+        }
+        let free: Vec<Coord> = all_cells().filter(|c| !occupied.contains(c)).collect();
+        let food_count = rng.gen_range(0..=8);
+        let food = free.choose_multiple(rng, food_count).copied().collect();
+        let teams = if rng.gen_bool(0.5) {
+            vec![vec![0, 1], vec![2, 3]]
+        } else {
+            vec![vec![0, 2], vec![1, 3]]
+        };
+        SimpleBoard::new(food, snakes, teams)
+    }
+
+    fn all_cells() -> impl Iterator<Item = Coord> {
+        (0..11).flat_map(|x| (0..11).map(move |y| Coord { x, y }))
+    }
+
+    fn neighbours(c: Coord) -> impl Iterator<Item = Coord> {
+        [(0, 1), (1, 0), (0, -1), (-1, 0)]
+            .iter()
+            .map(move |&(dx, dy)| Coord { x: c.x + dx, y: c.y + dy })
+            .filter(|c| (0..11).contains(&c.x) && (0..11).contains(&c.y))
+    }
+
+    // Both halves of a turn: our team moves, then the opponents move and the dead are removed
+    fn full_turns(board: &SimpleBoard) -> Vec<(JointMove, JointMove, SimpleBoard)> {
+        let mut turns = Vec::new();
+        for (ours, half) in board.simulate_move(true) {
+            for (theirs, next) in half.simulate_move(false) {
+                turns.push((ours.clone(), theirs, next));
+            }
+        }
+        turns
+    }
+
+    // The simulator falls back to an unchanged board when a side has no legal joint move,
+    // which the rules don't know about
+    fn is_fallback(before: &SimpleBoard, after: &SimpleBoard, moves: &JointMove) -> bool {
+        moves.iter().any(|m| {
+            before.snakes[m.id].as_ref().map(|s| s.head()) == after.snakes[m.id].as_ref().map(|s| s.head())
+        })
+    }
+
+    const SEEDS: u64 = 300;
+
+    #[test]
+    fn test_random_positions_are_legal() {
+        for seed in 0..SEEDS {
+            let board = random_board(&mut StdRng::seed_from_u64(seed));
+            let snakes: Vec<&SimpleSnake> = board.snakes.iter().flatten().collect();
+            for (i, snake) in snakes.iter().enumerate() {
+                // Consecutive segments touch or are stacked
+                for (a, b) in snake.body.iter().zip(snake.body.iter().skip(1)) {
+                    assert!((a.x - b.x).abs() + (a.y - b.y).abs() <= 1, "seed {}", seed);
+                }
+                for other in &snakes[i + 1..] {
+                    assert!(!other.body.iter().any(|c| snake.body.contains(c)), "seed {}", seed);
+                }
+                assert!(!board.food.iter().any(|f| snake.body.contains(f)), "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_simulate_move_invariants() {
+        for seed in 0..SEEDS {
+            let board = random_board(&mut StdRng::seed_from_u64(seed));
+            for (ours, theirs, next) in full_turns(&board) {
+                let ctx = format!("seed {} moves {:?} {:?}\n{}\n{}", seed, ours, theirs, board, next);
+                for (i, snake) in next.snakes.iter().enumerate() {
+                    let Some(snake) = snake else { continue };
+                    let before = board.snakes[i].as_ref().expect(&ctx);
+                    let ate = board.food.contains(&snake.head());
+
+                    // Dead snakes are removed
+                    assert!(snake.health > 0 && snake.health <= 100, "{}", ctx);
+                    assert!(!simple_out_of_bounds(&snake.head(), &Movement::None), "{}", ctx);
+
+                    // Length only changes by eating, health is reset by it
+                    assert_eq!(snake.body.len(), before.body.len() + ate as usize, "{}", ctx);
+                    assert_eq!(snake.health, if ate { 100 } else { before.health - 1 }, "{}", ctx);
+                    assert!(!next.food.contains(&snake.head()), "{}", ctx);
+
+                    // No living snake overlaps another or its own body
+                    assert!(!snake.body.iter().skip(1).any(|&c| c == snake.head()), "{}", ctx);
+                    for other in next.snakes.iter().flatten().filter(|&o| o != snake) {
+                        assert!(!other.body.iter().any(|c| snake.body.contains(c)), "{}", ctx);
+                    }
+                }
+            }
+        }
+    }
+
+    fn to_api_board(board: &SimpleBoard) -> Board {
+        let snakes = board
+            .snakes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| s.as_ref().map(|s| (i, s)))
+            .map(|(i, s)| {
+                let mut snake = api_snake(&i.to_string(), "", s.body.iter().copied().collect());
+                snake.health = s.health;
+                snake
+            })
+            .collect();
+        Board {
+            height: 11,
+            width: 11,
+            food: board.food.clone(),
+            snakes,
+            hazards: vec![],
+        }
+    }
+
+    // Every full turn the simulator produces must match the reference rules engine
+    #[test]
+    fn test_simulate_move_matches_rules() {
+        let mut compared = 0;
+        for seed in 0..SEEDS {
+            let board = random_board(&mut StdRng::seed_from_u64(seed));
+            let api_board = to_api_board(&board);
+            for (ours, half) in board.simulate_move(true) {
+                if is_fallback(&board, &half, &ours) {
+                    continue;
+                }
+                for (theirs, next) in half.simulate_move(false) {
+                    if is_fallback(&half, &next, &theirs) {
+                        continue;
+                    }
+                    let moves: HashMap<String, Movement> =
+                        ours.iter().chain(theirs.iter()).map(|m| (m.id.to_string(), m.mv)).collect();
+                    let expected = rules::step(&api_board, &moves, rules::HAZARD_DAMAGE);
+                    let ctx = format!("seed {} moves {:?} {:?}\n{}\n{}", seed, ours, theirs, board, next);
+                    assert_eq!(to_api_board(&next).to_string(), expected.to_string(), "{}", ctx);
+                    let actual: Vec<(String, i32, Vec<Coord>)> =
+                        to_api_board(&next).snakes.into_iter().map(|s| (s.id, s.health, s.body)).collect();
+                    let expected: Vec<(String, i32, Vec<Coord>)> =
+                        expected.snakes.into_iter().map(|s| (s.id, s.health, s.body)).collect();
+                    assert_eq!(actual, expected, "{}", ctx);
+                    compared += 1;
+                }
+            }
+        }
+        assert!(compared > 5_000, "only {} turns compared", compared);
+    }
 }
@@ -0,0 +1,211 @@
+// Tactical patterns against opponents.
+//
+// Our team moves first in the search, so after our move every opponent still has to pick
+// a move. An opponent without a safe move at that point is lost: it either runs into one
+// of our longer heads, is cut off against a wall, or is boxed in by two of our snakes.
+// These kills and the positions that leave an opponent with a single way out are used to
+// order our moves and as a bonus on top of the evaluation.
+
+use super::simple::SimpleBoard;
+use crate::config::config;
+use crate::Coord;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    // The only ways out lead into the head of a longer snake of ours
+    HeadToHead,
+    // The opponent is held against the edge of the board
+    WallCutOff,
+    // Two or more of our snakes block the ways out together
+    Pincer,
+    // Any other trap, for example against the opponent's own body
+    Trap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tactic {
+    pub victim: usize,
+    pub kind: Kind,
+}
+
+// Opponents left without a safe move on a board where the opponents move next
+pub fn kills(board: &SimpleBoard) -> Vec<Tactic> {
+    board
+        .opps()
+        .filter(|&i| matches!(board.snakes.get(i), Some(Some(_))))
+        .filter(|&i| board.safe_move_count(i) == 0)
+        .map(|victim| Tactic {
+            victim,
+            kind: classify(board, victim),
+        })
+        .collect()
+}
+
+fn classify(board: &SimpleBoard, victim: usize) -> Kind {
+    let snake = board.snakes[victim].as_ref().unwrap();
+    let head = snake.head();
+    let exits: Vec<Coord> = [(0, 1), (1, 0), (0, -1), (-1, 0)]
+        .iter()
+        .map(|&(dx, dy)| Coord {
+            x: head.x + dx,
+            y: head.y + dy,
+        })
+        .filter(|c| (0..11).contains(&c.x) && (0..11).contains(&c.y))
+        .collect();
+    let mut blockers = Vec::new();
+    let mut head_on = false;
+    for &i in board.team() {
+        let Some(Some(ours)) = board.snakes.get(i) else {
+            continue;
+        };
+        if exits.iter().any(|c| ours.body().contains(c)) {
+            blockers.push(i);
+        }
+        if exits.contains(&ours.head()) && ours.body().len() > snake.body().len() {
+            head_on = true;
+        }
+    }
+    if blockers.len() >= 2 {
+        Kind::Pincer
+    } else if head_on {
+        Kind::HeadToHead
+    } else if exits.len() < 4 {
+        Kind::WallCutOff
+    } else {
+        Kind::Trap
+    }
+}
+
+// Bonus for the kills and squeezes on a board where the opponents move next
+pub fn score(board: &SimpleBoard) -> i32 {
+    let weights = &config().weights;
+    board
+        .opps()
+        .filter(|&i| matches!(board.snakes.get(i), Some(Some(_))))
+        .map(|i| match board.safe_move_count(i) {
+            0 => weights.kill,
+            1 => weights.squeeze,
+            _ => 0,
+        })
+        .sum()
+}
+
+// Whether the next move can decide a head-to-head or close a trap: two heads of
+// different teams could meet, or a snake is down to a single safe move. Snakes that
+// already moved this turn only meet a head next to them.
+pub fn imminent(board: &SimpleBoard, our_team: bool) -> bool {
+    let heads: Vec<(usize, Coord, bool)> = board
+        .snakes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| {
+            let team = board.team_of(i)?;
+            Some((team, s.as_ref()?.head(), our_team || team != 0))
+        })
+        .collect();
+    let meet = heads.iter().enumerate().any(|(a, &(team, head, moving))| {
+        heads[a + 1..].iter().any(|&(other_team, other, other_moving)| {
+            let reach = moving as i32 + other_moving as i32;
+            team != other_team && reach > 0 && (head.x - other.x).abs() + (head.y - other.y).abs() <= reach
+        })
+    });
+    meet || (0..board.snakes.len()).any(|i| board.snakes[i].is_some() && board.safe_move_count(i) <= 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{imminent, kills, score, Kind, Tactic};
+    use crate::config::config;
+    use crate::logic::simple::{Movement, SimpleBoard, SimpleSnake};
+    use crate::Coord;
+
+    fn snake(body: &[(i32, i32)]) -> Option<SimpleSnake> {
+        Some(SimpleSnake::new(body.iter().map(|&(x, y)| Coord { x, y }).collect(), 100))
+    }
+
+    // Board after our team moved with the given joint move
+    fn after(board: &SimpleBoard, moves: &[Movement]) -> SimpleBoard {
+        board
+            .simulate_move(true)
+            .into_iter()
+            .find(|(m, _)| m.iter().map(|s| s.mv).eq(moves.iter().copied()))
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn test_head_to_head_kill() {
+        // The opponent in the corner can only go right, where our longer snake gets first
+        let board = SimpleBoard::new(
+            vec![],
+            vec![snake(&[(2, 0), (3, 0), (4, 0), (5, 0)]), snake(&[(0, 0), (0, 1), (1, 1)])],
+            vec![vec![0], vec![1]],
+        );
+        let next = after(&board, &[Movement::Left]);
+        assert_eq!(
+            kills(&next),
+            vec![Tactic {
+                victim: 1,
+                kind: Kind::HeadToHead
+            }]
+        );
+        assert_eq!(score(&next), config().weights.kill);
+    }
+
+    #[test]
+    fn test_wall_cut_off() {
+        // Running along the bottom edge, our snake moves in front of the opponent
+        let board = SimpleBoard::new(
+            vec![],
+            vec![snake(&[(5, 1), (4, 1), (3, 1)]), snake(&[(4, 0), (3, 0), (2, 0)])],
+            vec![vec![0], vec![1]],
+        );
+        let next = after(&board, &[Movement::Down]);
+        assert_eq!(kills(&next)[0].kind, Kind::WallCutOff);
+        assert!(kills(&after(&board, &[Movement::Up])).is_empty());
+        assert_eq!(score(&after(&board, &[Movement::Right])), config().weights.squeeze);
+    }
+
+    #[test]
+    fn test_pincer() {
+        let board = SimpleBoard::new(
+            vec![],
+            vec![
+                snake(&[(4, 6), (4, 5), (3, 5)]),
+                snake(&[(6, 4), (7, 4), (8, 4)]),
+                snake(&[(5, 5), (5, 4), (4, 4)]),
+            ],
+            vec![vec![0, 1], vec![2]],
+        );
+        let next = after(&board, &[Movement::Right, Movement::Up]);
+        assert_eq!(kills(&next)[0].kind, Kind::Pincer);
+    }
+
+    #[test]
+    fn test_imminent() {
+        // Two cells apart in the open, both heads can reach the cell between them
+        let board = SimpleBoard::new(
+            vec![],
+            vec![snake(&[(3, 5), (2, 5), (1, 5)]), snake(&[(5, 5), (6, 5), (7, 5)])],
+            vec![vec![0], vec![1]],
+        );
+        assert!(imminent(&board, true));
+        // Once we moved away only a head next to ours counts
+        assert!(!imminent(&after(&board, &[Movement::Up]), false));
+        assert!(imminent(&after(&board, &[Movement::Right]), false));
+
+        let apart = SimpleBoard::new(
+            vec![],
+            vec![snake(&[(3, 5), (2, 5), (1, 5)]), snake(&[(8, 5), (9, 5), (10, 5)])],
+            vec![vec![0], vec![1]],
+        );
+        assert!(!imminent(&apart, true));
+        // In the corner the opponent is down to one way out
+        let cornered = SimpleBoard::new(
+            vec![],
+            vec![snake(&[(3, 5), (2, 5), (1, 5)]), snake(&[(0, 0), (0, 1), (1, 1)])],
+            vec![vec![0], vec![1]],
+        );
+        assert!(imminent(&cornered, true));
+    }
+}
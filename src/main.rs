@@ -4,18 +4,44 @@ extern crate rocket;
 use log::info;
 use rocket::fairing::AdHoc;
 use rocket::http::Status;
+use rocket::response::content::RawHtml;
 use rocket::serde::{json::Json, Deserialize};
 use serde::Serialize;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::env;
-use rocket::{get, launch, routes, State};
+use std::path::Path;
+use rocket::{get, routes, Build, Rocket, State};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
+mod appearance;
+mod cli;
+mod config;
+mod logging;
 mod logic;
+// Text positions for tests, fixtures and perft
+mod notation;
+mod peer;
+mod render;
+mod replay;
+// Ground truth for the simulator, only exercised by the tests
+#[cfg(test)]
+mod rules;
+use appearance::Appearance;
+use config::{config, Config};
+use logging::Context;
+use logic::opening::OpeningBook;
+use logic::bots::Bot;
+use logic::emergency;
+use logic::latency::LatencyTracker;
+use logic::opponent::OpponentTracker;
+use logic::shout::Intent;
 use logic::simple::Movement;
+use peer::{PeerAuth, PeerConfig, PeerHello, PeerPlan, Peers, SharedPeers};
 
 type SharedData = Arc<Mutex<HashMap<String, GameInfo>>>;
+type SharedBook = Arc<OpeningBook>;
 
 // API and Response Objects
 // See https://docs.battlesnake.com/api
@@ -38,32 +64,7 @@ pub struct Board {
 
 impl std::fmt::Display for Board {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        /* build board representation string */
-        let mut board: String = "\n|:---------:|".to_owned();
-        for y in (0..self.height).rev() {
-            board += "\n|";
-            for x in 0..self.width {
-                let coord = Coord { x: x as i32, y: y as i32 };
-                let piece: String = if self.food.contains(&coord) {
-                    "f".to_string()
-                } else if self.hazards.contains(&coord) {
-                    "b".to_string()
-                } else if let Some(snake) = self.snakes.iter().find(|s| s.body.contains(&coord)) {
-                    if snake.body[0] == coord {
-                        "h".to_string()
-                    } else {
-                        "s".to_string()
-                    }
-                } else {
-                    " ".to_string()
-                };
-                board += &piece;
-            }
-            board += "|";
-        }
-        board += "\n|:---------:|";
-
-        write!(f, "{}", board)
+        write!(f, "\n{}", render::terminal(&render::Scene::from(self), false))
     }
 }
 
@@ -77,6 +78,8 @@ pub struct Battlesnake {
     length: i32,
     latency: String,
     shout: Option<String>,
+    #[serde(default)]
+    squad: String,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
@@ -100,19 +103,65 @@ pub struct GameState {
 }
 
 pub struct GameInfo {
+    #[allow(dead_code)]
     id: String,
     timeout: u32,
-    agent_ids: [String; 2],
-    agent_moves: [Vec<Movement>; 2],
+    // ids of our snakes in this game, all of them are searched as one team
+    agent_ids: Vec<String>,
+    agent_moves: Vec<Vec<Movement>>,
+    // ids of team mates announced by peer servers
+    peer_ids: Vec<String>,
+    // what team mates on other servers shouted they will do this turn
+    intents: HashMap<String, Intent>,
+    // moves the other snakes made so far in this game
+    opponents: OpponentTracker,
+    // round trip times of our answers, they decide how much of the timeout is searched
+    latency: LatencyTracker,
+}
+
+impl GameInfo {
+    pub fn new(id: String, timeout: u32, agent_ids: Vec<String>) -> Self {
+        GameInfo {
+            id,
+            timeout,
+            agent_moves: agent_ids.iter().map(|_| vec![]).collect(),
+            agent_ids,
+            peer_ids: vec![],
+            intents: HashMap::new(),
+            opponents: OpponentTracker::default(),
+            latency: LatencyTracker::default(),
+        }
+    }
+
+    // Keeps one move per turn for our snake after a turn get_move didn't finish, so the
+    // moves team mates store for it stay lined up with the turns
+    fn played(&mut self, agent_id: &str, turn: i32, chosen: Movement) {
+        if let Some(idx) = self.agent_ids.iter().position(|a| a == agent_id) {
+            let moves = &mut self.agent_moves[idx];
+            let turn = turn.max(0) as usize;
+            if moves.len() < turn {
+                moves.resize(turn, chosen);
+            }
+            // get_move may have recorded the turn before it failed
+            if moves.len() == turn {
+                moves.push(chosen);
+            }
+        }
+    }
 }
 
 #[get("/")]
-fn handle_index() -> Json<Value> {
-    Json(logic::info())
+fn handle_index(appearance: &State<Appearance>) -> Json<Value> {
+    Json(logic::info(appearance))
 }
 
 #[post("/start", format = "json", data = "<start_req>")]
-fn handle_start(shared_data: &State<SharedData>, start_req: Json<GameState>) -> Status {
+fn handle_start(
+    shared_data: &State<SharedData>,
+    peers: &State<SharedPeers>,
+    start_req: Json<GameState>,
+) -> Status {
+    let _context = Context::request(&start_req.game.id, start_req.turn, &start_req.you.id).enter();
     // Store game information in shared data
     let mut data = shared_data.lock().unwrap();
     // Check if the game ID already exists
@@ -121,18 +170,24 @@ fn handle_start(shared_data: &State<SharedData>, start_req: Json<GameState>) ->
     if data.contains_key(&game_id) {
         // Add agent ID to the existing game info
         if let Some(game_info) = data.get_mut(&start_req.game.id) {
-            game_info.agent_ids[1] = you_id.clone();
+            if !game_info.agent_ids.contains(&you_id) {
+                game_info.agent_ids.push(you_id.clone());
+                game_info.agent_moves.push(vec![]);
+            }
         }
     } else {
         // Create a new game info entry
-        let game_info = GameInfo {
-            id: game_id.clone(),
-            timeout: start_req.game.timeout-25,
-            agent_ids: [you_id.clone(), String::new()],
-            agent_moves: [vec![], vec![]],
-        };
+        let game_info = GameInfo::new(
+            game_id.clone(),
+            start_req.game.timeout,
+            vec![you_id.clone()],
+        );
         data.insert(game_id.clone(), game_info);
     }
+    peers.send_hello(&PeerHello {
+        game_id: game_id.clone(),
+        agent_id: you_id.clone(),
+    });
     logic::start(
         &start_req.game,
         &start_req.turn,
@@ -144,27 +199,85 @@ fn handle_start(shared_data: &State<SharedData>, start_req: Json<GameState>) ->
 }
 
 #[post("/move", format = "json", data = "<move_req>")]
-fn handle_move(shared_data: &State<SharedData>, move_req: Json<GameState>) -> Json<Value> {
-    // Retrieve game information from shared data
-    let mut data = shared_data.lock().unwrap();
-    let game_id = move_req.game.id.clone();
-    let game_info = data.get_mut(&game_id).unwrap_or_else(|| {
-        panic!("Game ID {} not found in shared data", game_id)
-    });
-    let response = logic::get_move(
-        &move_req.game,
-        &move_req.turn,
-        &move_req.board,
-        &move_req.you,
-        game_info
-    );
+async fn handle_move(
+    shared_data: &State<SharedData>,
+    peers: &State<SharedPeers>,
+    book: &State<SharedBook>,
+    move_req: Json<GameState>,
+) -> Json<Value> {
+    let shared_data = shared_data.inner().clone();
+    let peers = peers.inner().clone();
+    let book = book.inner().clone();
+    // The deadline counts from here, waiting for the game data and everything before and
+    // after the search comes out of the same time
+    let received = Instant::now();
+    // The search blocks for most of the timeout, keep it off the async workers so
+    // peer requests can still be answered in the meantime
+    let context = Context::request(&move_req.game.id, move_req.turn, &move_req.you.id);
+    let response = rocket::tokio::task::spawn_blocking(move || {
+        let _context = context.enter();
+        // Retrieve game information from shared data
+        let mut data = shared_data.lock().unwrap();
+        let game_id = move_req.game.id.clone();
+        // A failing search still answers, with a move that doesn't depend on it
+        let answer = emergency::guarded(&move_req, || {
+            let game_info = data.get_mut(&game_id).unwrap_or_else(|| {
+                panic!("Game ID {} not found in shared data", game_id)
+            });
+            logic::get_move(
+                &move_req.game,
+                &move_req.turn,
+                &move_req.board,
+                &move_req.you,
+                game_info,
+                &peers,
+                &book,
+                received,
+            )
+        });
+        let mut game_info = data.get_mut(&game_id);
+        let response = match answer {
+            Ok(response) => response,
+            Err(chosen) => {
+                if let Some(game_info) = game_info.as_deref_mut() {
+                    game_info.played(&move_req.you.id, move_req.turn, chosen);
+                }
+                serde_json::json!({ "move": chosen })
+            }
+        };
+        if let Some(game_info) = game_info {
+            let elapsed = received.elapsed().as_millis() as u32;
+            game_info.latency.answered(&move_req.you.id, move_req.turn, elapsed);
+        }
+        response
+    })
+    .await
+    .unwrap();
 
     Json(response)
 }
 
 #[post("/end", format = "json", data = "<end_req>")]
-fn handle_end(shared_data: &State<SharedData>, end_req: Json<GameState>) -> Status {
+fn handle_end(
+    shared_data: &State<SharedData>,
+    peers: &State<SharedPeers>,
+    end_req: Json<GameState>,
+) -> Status {
+    let _context = Context::request(&end_req.game.id, end_req.turn, &end_req.you.id).enter();
     logic::end(&end_req.game, &end_req.turn, &end_req.board, &end_req.you);
+    replay::record(
+        &config().replays.dir,
+        &end_req.game.id,
+        &replay::Record {
+            turn: end_req.turn,
+            snake: end_req.you.id.clone(),
+            board: end_req.board.clone(),
+            chosen: None,
+            value: None,
+            depth: None,
+        },
+    );
+    peers.end_game(&end_req.game.id);
     // Store game information in shared data
     let mut data = shared_data.lock().unwrap();
     // Check if the game ID already exists
@@ -177,8 +290,63 @@ fn handle_end(shared_data: &State<SharedData>, end_req: Json<GameState>) -> Stat
     Status::Ok
 }
 
-#[launch]
-fn rocket() -> _ {
+#[post("/peer/hello", format = "json", data = "<hello>")]
+fn handle_peer_hello(_auth: PeerAuth, peers: &State<SharedPeers>, hello: Json<PeerHello>) -> Status {
+    peers.receive_hello(hello.into_inner());
+    Status::Ok
+}
+
+#[post("/peer/plan", format = "json", data = "<plan>")]
+fn handle_peer_plan(_auth: PeerAuth, peers: &State<SharedPeers>, plan: Json<PeerPlan>) -> Status {
+    peers.receive_plan(plan.into_inner());
+    Status::Ok
+}
+
+// Baseline bots to spar against, served from /bots/<name>/ by the same process
+
+#[get("/bots/<name>")]
+fn handle_bot_index(name: &str) -> Option<Json<Value>> {
+    Bot::from_name(name).map(|bot| Json(bot.info()))
+}
+
+#[post("/bots/<name>/start", format = "json", data = "<_start_req>")]
+fn handle_bot_start(name: &str, _start_req: Json<GameState>) -> Option<Status> {
+    Bot::from_name(name).map(|_| Status::Ok)
+}
+
+#[post("/bots/<name>/move", format = "json", data = "<move_req>")]
+async fn handle_bot_move(name: &str, move_req: Json<GameState>) -> Option<Json<Value>> {
+    let bot = Bot::from_name(name)?;
+    let context = Context::request(&move_req.game.id, move_req.turn, &move_req.you.id);
+    let chosen = rocket::tokio::task::spawn_blocking(move || {
+        let _context = context.enter();
+        bot.choose(&move_req)
+    })
+    .await
+    .unwrap();
+    Some(Json(serde_json::json!({ "move": chosen })))
+}
+
+#[post("/bots/<name>/end", format = "json", data = "<_end_req>")]
+fn handle_bot_end(name: &str, _end_req: Json<GameState>) -> Option<Status> {
+    Bot::from_name(name).map(|_| Status::Ok)
+}
+
+// Recorded games, see replay.rs
+
+#[get("/replays")]
+fn handle_replays() -> RawHtml<String> {
+    RawHtml(replay::index_html(&replay::list(&config().replays.dir)))
+}
+
+#[get("/replays/<game_id>")]
+fn handle_replay(game_id: &str) -> Option<RawHtml<String>> {
+    let frames = replay::load(&config().replays.dir, game_id)?;
+    Some(RawHtml(replay::viewer_html(game_id, &frames)))
+}
+
+#[rocket::main]
+async fn main() {
     // Lots of web hosting services expect you to bind to the port specified by the `PORT`
     // environment variable. However, Rocket looks at the `ROCKET_PORT` environment variable.
     // If we find a value for `PORT`, we set `ROCKET_PORT` to that value.
@@ -186,27 +354,88 @@ fn rocket() -> _ {
         env::set_var("ROCKET_PORT", &port);
     }
 
-    // We default to 'info' level logging. But if the `RUST_LOG` environment variable is set,
-    // we keep that value instead.
+    let config = match Config::load(&Config::figment()) {
+        Ok(config) => config,
+        Err(errors) => {
+            eprintln!("Invalid configuration:");
+            for e in errors {
+                eprintln!("  {}", e);
+            }
+            std::process::exit(1);
+        }
+    };
+
+    // We default to the configured level of logging. But if the `RUST_LOG` environment
+    // variable is set, we keep that value instead.
     if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", "info");
+        env::set_var("RUST_LOG", &config.logging.level);
     }
+    let log_format = config.logging.format.clone();
+    config::install(config);
+
+    logging::init(&log_format);
 
-    env_logger::init();
+    // Subcommands such as `book` run instead of the server
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
 
+    if let Err(e) = rocket().launch().await {
+        eprintln!("Server failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn rocket() -> Rocket<Build> {
     info!("Starting Battlesnake Server...");
     let initial_data: HashMap<String, GameInfo> = HashMap::new();
     let shared_data = Arc::new(Mutex::new(initial_data));
+    let peer_config = PeerConfig::from_config(config());
+    if let Some(config) = &peer_config {
+        info!("Coordinating with peers {:?}", config.urls);
+    }
+    let peers: SharedPeers = Arc::new(Peers::new(peer_config));
+    let book_path = &config().book.path;
+    let book = match OpeningBook::load(Path::new(&book_path)) {
+        Ok(book) => {
+            info!("Loaded {} opening book positions from {}", book.len(), book_path);
+            book
+        }
+        Err(e) => {
+            info!("No opening book loaded from {}: {}", book_path, e);
+            OpeningBook::default()
+        }
+    };
+    let book: SharedBook = Arc::new(book);
+    let appearance: Appearance = config().appearance.clone();
+    info!("Playing as {} {} / {} by {}", appearance.color, appearance.head, appearance.tail, appearance.author);
 
-    rocket::build()
+    rocket::custom(config().rocket())
         .attach(AdHoc::on_response("Server ID Middleware", |_, res| {
             Box::pin(async move {
                 res.set_raw_header("Server", "battlesnake/github/starter-snake-rust");
             })
         }))
         .manage(shared_data)
+        .manage(peers)
+        .manage(book)
+        .manage(appearance)
         .mount(
             "/",
-            routes![handle_index, handle_start, handle_move, handle_end],
+            routes![
+                handle_index,
+                handle_start,
+                handle_move,
+                handle_end,
+                handle_peer_hello,
+                handle_peer_plan,
+                handle_bot_index,
+                handle_bot_start,
+                handle_bot_move,
+                handle_bot_end,
+                handle_replays,
+                handle_replay
+            ],
         )
 }
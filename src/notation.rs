@@ -0,0 +1,450 @@
+// Compact text positions for tests, fixtures and the command line tools.
+//
+// A position is a grid drawing with the top row first, followed by one annotation line
+// per snake that needs one:
+//
+//     . . . . *
+//     . A a a .
+//     . . . a .
+//     B b . . .
+//     A: health=40
+//     B: team=1 stack=1
+//
+// Every snake has a letter, upper case for its head and lower case for the rest of its
+// body, `*` is food, `~` a hazard and `.` an empty cell. Spaces between cells are
+// optional. The body is traced from the head through the snake's cells; where that
+// leaves more than one way, `tail=x,y` names the last cell. Annotations are `health`
+// (default 100), `team` (default 0 for A and 1 for everyone else, team 0 is ours),
+// `stack` for the number of extra segments on the tail after eating, and `dead` for a
+// snake that is no longer on the board. Letters that are skipped are dead snakes too.
+//
+// For a SimpleBoard the grid may be smaller than 11x11, it is then the bottom left
+// corner of the board.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::logic::simple::{SimpleBoard, SimpleSnake};
+use crate::{Battlesnake, Board, Coord};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snake {
+    pub body: Vec<Coord>,
+    pub health: i32,
+    pub team: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub width: i32,
+    pub height: i32,
+    pub food: Vec<Coord>,
+    pub hazards: Vec<Coord>,
+    // Indexed by letter, None for dead snakes
+    pub snakes: Vec<Option<Snake>>,
+}
+
+#[derive(Default)]
+struct Annotation {
+    health: Option<i32>,
+    team: Option<usize>,
+    stack: usize,
+    tail: Option<Coord>,
+    dead: bool,
+}
+
+fn default_team(slot: usize) -> usize {
+    if slot == 0 {
+        0
+    } else {
+        1
+    }
+}
+
+fn slot(letter: char) -> usize {
+    (letter.to_ascii_lowercase() as u8 - b'a') as usize
+}
+
+fn letter(slot: usize, head: bool) -> char {
+    let c = (b'a' + slot as u8) as char;
+    if head {
+        c.to_ascii_uppercase()
+    } else {
+        c
+    }
+}
+
+fn parse_coord(value: &str) -> Option<Coord> {
+    let (x, y) = value.split_once(',')?;
+    Some(Coord {
+        x: x.trim().parse().ok()?,
+        y: y.trim().parse().ok()?,
+    })
+}
+
+fn parse_annotation(line: &str) -> Result<(usize, Annotation), String> {
+    let (name, rest) = line.split_once(':').unwrap();
+    let mut chars = name.trim().chars();
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_uppercase() => c,
+        _ => return Err(format!("annotation for unknown snake {:?}", name)),
+    };
+    let mut annotation = Annotation::default();
+    for item in rest.split_whitespace() {
+        let (key, value) = item.split_once('=').unwrap_or((item, ""));
+        let invalid = || format!("invalid {} for snake {}: {:?}", key, c, value);
+        match key {
+            "health" => annotation.health = Some(value.parse().map_err(|_| invalid())?),
+            "team" => annotation.team = Some(value.parse().map_err(|_| invalid())?),
+            "stack" => annotation.stack = value.parse().map_err(|_| invalid())?,
+            "tail" => annotation.tail = Some(parse_coord(value).ok_or_else(invalid)?),
+            "dead" => annotation.dead = true,
+            _ => return Err(format!("unknown annotation {:?} for snake {}", key, c)),
+        }
+    }
+    Ok((slot(c), annotation))
+}
+
+// Orders the cells of a snake into a body that starts at the head, visits every cell
+// once and ends at the tail when one is given
+fn trace(head: Coord, cells: &[Coord], tail: Option<Coord>) -> Result<Vec<Coord>, String> {
+    fn extend(path: &mut Vec<Coord>, cells: &[Coord], tail: Option<Coord>, found: &mut Vec<Vec<Coord>>) {
+        if found.len() > 1 {
+            return;
+        }
+        if path.len() == cells.len() + 1 {
+            if tail.is_none_or(|t| path.last() == Some(&t)) {
+                found.push(path.clone());
+            }
+            return;
+        }
+        let last = *path.last().unwrap();
+        for &c in cells {
+            if (c.x - last.x).abs() + (c.y - last.y).abs() == 1 && !path.contains(&c) {
+                path.push(c);
+                extend(path, cells, tail, found);
+                path.pop();
+            }
+        }
+    }
+
+    let mut found = Vec::new();
+    extend(&mut vec![head], cells, tail, &mut found);
+    match found.len() {
+        0 => Err(format!("body at {} is not connected", head)),
+        1 => Ok(found.pop().unwrap()),
+        _ => Err(format!("body at {} can be traced more than one way, add tail=x,y", head)),
+    }
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let lines: Vec<&str> = text.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+        let (annotation_lines, grid): (Vec<&str>, Vec<&str>) = lines.into_iter().partition(|l| l.contains(':'));
+        let rows: Vec<Vec<char>> = grid
+            .iter()
+            .map(|l| l.chars().filter(|c| !c.is_whitespace()).collect())
+            .collect();
+        let height = rows.len() as i32;
+        let width = rows.first().map_or(0, |r| r.len() as i32);
+        if height == 0 || rows.iter().any(|r| r.len() as i32 != width) {
+            return Err("the grid must have at least one row and rows of equal length".to_string());
+        }
+
+        let mut food = Vec::new();
+        let mut hazards = Vec::new();
+        let mut heads: BTreeMap<usize, Coord> = BTreeMap::new();
+        let mut cells: BTreeMap<usize, Vec<Coord>> = BTreeMap::new();
+        for (row, chars) in rows.iter().enumerate() {
+            for (x, &c) in chars.iter().enumerate() {
+                let coord = Coord {
+                    x: x as i32,
+                    y: height - 1 - row as i32,
+                };
+                match c {
+                    '.' => {}
+                    '*' => food.push(coord),
+                    '~' => hazards.push(coord),
+                    'A'..='Z' => {
+                        if heads.insert(slot(c), coord).is_some() {
+                            return Err(format!("snake {} has more than one head", c));
+                        }
+                    }
+                    'a'..='z' => cells.entry(slot(c)).or_default().push(coord),
+                    _ => return Err(format!("unknown cell {:?} at {}", c, coord)),
+                }
+            }
+        }
+
+        let mut annotations = BTreeMap::new();
+        for line in annotation_lines {
+            let (slot, annotation) = parse_annotation(line)?;
+            annotations.insert(slot, annotation);
+        }
+        let count = heads
+            .keys()
+            .chain(cells.keys())
+            .chain(annotations.keys())
+            .max()
+            .map_or(0, |&s| s + 1);
+        let mut snakes = Vec::new();
+        for s in 0..count {
+            let annotation = annotations.remove(&s).unwrap_or_default();
+            let head = heads.get(&s);
+            if annotation.dead || head.is_none() {
+                if head.is_some() || cells.contains_key(&s) {
+                    return Err(format!("snake {} is dead or has no head but is on the board", letter(s, true)));
+                }
+                snakes.push(None);
+                continue;
+            }
+            let mut body = trace(*head.unwrap(), cells.get(&s).map_or(&[][..], |c| &c[..]), annotation.tail)?;
+            let tail = *body.last().unwrap();
+            body.extend(std::iter::repeat_n(tail, annotation.stack));
+            snakes.push(Some(Snake {
+                body,
+                health: annotation.health.unwrap_or(100),
+                team: annotation.team.unwrap_or_else(|| default_team(s)),
+            }));
+        }
+
+        Ok(Position {
+            width,
+            height,
+            food,
+            hazards,
+            snakes,
+        })
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rows = Vec::new();
+        for y in (0..self.height).rev() {
+            let row: Vec<String> = (0..self.width)
+                .map(|x| {
+                    let c = Coord { x, y };
+                    let snake = self.snakes.iter().enumerate().find_map(|(i, s)| {
+                        let pos = s.as_ref()?.body.iter().position(|&b| b == c)?;
+                        Some(letter(i, pos == 0))
+                    });
+                    match snake {
+                        Some(l) => l,
+                        None if self.food.contains(&c) => '*',
+                        None if self.hazards.contains(&c) => '~',
+                        None => '.',
+                    }
+                    .to_string()
+                })
+                .collect();
+            rows.push(row.join(" "));
+        }
+        for (i, snake) in self.snakes.iter().enumerate() {
+            let mut items = Vec::new();
+            match snake {
+                None => items.push("dead".to_string()),
+                Some(snake) => {
+                    if snake.health != 100 {
+                        items.push(format!("health={}", snake.health));
+                    }
+                    if snake.team != default_team(i) {
+                        items.push(format!("team={}", snake.team));
+                    }
+                    let tail = *snake.body.last().unwrap();
+                    let stack = snake.body.iter().rev().skip(1).take_while(|&&c| c == tail).count();
+                    if stack > 0 {
+                        items.push(format!("stack={}", stack));
+                    }
+                    // The tail is only written when the body can't be traced without it
+                    let cells: Vec<Coord> = snake.body[1..snake.body.len() - stack].to_vec();
+                    if trace(snake.body[0], &cells, None).as_deref() != Ok(&snake.body[..snake.body.len() - stack]) {
+                        items.push(format!("tail={},{}", tail.x, tail.y));
+                    }
+                }
+            }
+            if !items.is_empty() {
+                rows.push(format!("{}: {}", letter(i, true), items.join(" ")));
+            }
+        }
+        write!(f, "{}", rows.join("\n"))
+    }
+}
+
+impl Position {
+    pub fn simple_board(&self) -> SimpleBoard {
+        assert!(self.width <= 11 && self.height <= 11, "a SimpleBoard is at most 11x11");
+        let snakes = self
+            .snakes
+            .iter()
+            .map(|s| s.as_ref().map(|s| SimpleSnake::new(s.body.clone(), s.health)))
+            .collect();
+        SimpleBoard::new(self.food.clone(), snakes, self.teams())
+    }
+
+    // Snakes get their letter as id and name and their team as squad
+    pub fn board(&self) -> Board {
+        let snakes = self
+            .snakes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| s.as_ref().map(|s| (i, s)))
+            .map(|(i, s)| Battlesnake {
+                id: letter(i, false).to_string(),
+                name: letter(i, false).to_string(),
+                health: s.health,
+                body: s.body.clone(),
+                head: s.body[0],
+                length: s.body.len() as i32,
+                latency: "0".to_string(),
+                shout: None,
+                squad: s.team.to_string(),
+            })
+            .collect();
+        Board {
+            height: self.height as u32,
+            width: self.width,
+            food: self.food.clone(),
+            snakes,
+            hazards: self.hazards.clone(),
+        }
+    }
+
+    // Snake indices grouped by team number, team 0 first. Dead snakes belong to the
+    // team of their letter's default.
+    fn teams(&self) -> Vec<Vec<usize>> {
+        let mut teams: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        teams.entry(0).or_default();
+        for (i, s) in self.snakes.iter().enumerate() {
+            let team = s.as_ref().map_or_else(|| default_team(i), |s| s.team);
+            teams.entry(team).or_default().push(i);
+        }
+        teams.into_values().filter(|t| !t.is_empty()).collect()
+    }
+}
+
+impl From<&SimpleBoard> for Position {
+    fn from(board: &SimpleBoard) -> Self {
+        let snakes = board
+            .snakes
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                s.as_ref().map(|s| Snake {
+                    body: s.body().iter().copied().collect(),
+                    health: s.health(),
+                    team: board.team_of(i).unwrap_or_else(|| default_team(i)),
+                })
+            })
+            .collect();
+        Position {
+            width: 11,
+            height: 11,
+            food: board.food.clone(),
+            hazards: vec![],
+            snakes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Position;
+    use crate::logic::simple::Movement;
+    use crate::Coord;
+
+    #[test]
+    fn test_parse() {
+        let position: Position = "
+            . . . . *
+            . A a a .
+            . . . a .
+            B b . . ~
+            A: health=40
+            B: stack=1
+        "
+        .parse()
+        .unwrap();
+        assert_eq!((position.width, position.height), (5, 4));
+        assert_eq!(position.food, vec![Coord { x: 4, y: 3 }]);
+        assert_eq!(position.hazards, vec![Coord { x: 4, y: 0 }]);
+        let a = position.snakes[0].as_ref().unwrap();
+        assert_eq!(a.body, vec![Coord { x: 1, y: 2 }, Coord { x: 2, y: 2 }, Coord { x: 3, y: 2 }, Coord { x: 3, y: 1 }]);
+        assert_eq!((a.health, a.team), (40, 0));
+        let b = position.snakes[1].as_ref().unwrap();
+        assert_eq!(b.body, vec![Coord { x: 0, y: 0 }, Coord { x: 1, y: 0 }, Coord { x: 1, y: 0 }]);
+        assert_eq!(b.team, 1);
+    }
+
+    #[test]
+    fn test_coiled_body_needs_tail() {
+        // The snake from test_own_tail in simple.rs, traced either way round
+        let coil = "
+            a a .
+            a A a
+            a a a
+        ";
+        assert!(coil.parse::<Position>().is_err());
+        let position: Position = format!("{}\nA: tail=2,1", coil).parse().unwrap();
+        let body = &position.snakes[0].as_ref().unwrap().body;
+        assert_eq!(body.len(), 8);
+        assert_eq!(body[1], Coord { x: 1, y: 2 });
+        assert_eq!(body[7], Coord { x: 2, y: 1 });
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!("A a\n. .\na .".parse::<Position>().is_err());
+        assert!("A A".parse::<Position>().is_err());
+        assert!(". b".parse::<Position>().is_err());
+        assert!("A .\nA: speed=3".parse::<Position>().is_err());
+        assert!("A . .\n. .".parse::<Position>().is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "
+            . . C c .
+            a a a c *
+            a A a . ~
+            . . B b b
+            A: health=7 tail=0,1
+            B: stack=2
+            C: team=2
+            D: dead
+        ";
+        let position: Position = text.parse().unwrap();
+        let written = position.to_string();
+        assert_eq!(written.parse::<Position>().unwrap(), position);
+
+        // The same position as a SimpleBoard and back
+        let board = position.simple_board();
+        assert_eq!(board.teams(), &[vec![0], vec![1, 3], vec![2]]);
+        assert!(board.snakes[3].is_none());
+        let mut back = Position::from(&board);
+        back.width = position.width;
+        back.height = position.height;
+        back.hazards = position.hazards.clone();
+        assert_eq!(back, position);
+
+        let api = position.board();
+        assert_eq!(api.snakes.len(), 3);
+        assert_eq!(api.snakes[2].squad, "2");
+    }
+
+    #[test]
+    fn test_simple_board_from_corner() {
+        let board = "
+            A a a
+            . . .
+        "
+        .parse::<Position>()
+        .unwrap()
+        .simple_board();
+        // The grid is the bottom left corner, so row 1 is not the top wall
+        assert_eq!(board.safe_moves(0), vec![Movement::Up, Movement::Down]);
+    }
+}
@@ -0,0 +1,347 @@
+// Coordination between snake servers that play in the same team.
+//
+// Every server that is started with team.peer_urls announces its snakes to the listed peers
+// when a game starts and posts its search result on every move. Each server then plays
+// the best of its own plan and the plans it received in time, or its own plan alone if
+// the peers stay quiet. Peers are only enabled together with team.peer_token, which
+// every peer request has to carry.
+
+use log::info;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::logic::simple::Movement;
+
+#[derive(Debug, Clone)]
+pub struct PeerConfig {
+    // Base urls of the peers, for example http://10.0.0.2:8000
+    pub urls: Vec<String>,
+    // Shared secret sent as a bearer token on every peer request
+    pub token: String,
+    // Milliseconds of the move timeout that are kept for exchanging plans
+    pub margin: u32,
+}
+
+impl PeerConfig {
+    // None when no peers are configured and every server searches on its own, the
+    // settings make sure a token comes with the urls
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let team = &config.team;
+        if team.peer_urls.is_empty() || team.peer_token.is_empty() {
+            return None;
+        }
+        Some(PeerConfig {
+            urls: team.peer_urls.clone(),
+            token: team.peer_token.clone(),
+            margin: config.time.peer_margin_ms,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PeerHello {
+    pub game_id: String,
+    pub agent_id: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PeerPlan {
+    pub game_id: String,
+    pub turn: i32,
+    pub sender_id: String,
+    // Planned move for every snake in the team, keyed by snake id
+    pub moves: HashMap<String, Movement>,
+    pub value: i32,
+}
+
+impl PeerPlan {
+    // Plans are compared the same way on every server so that they agree on a winner
+    pub fn better_than(&self, other: &PeerPlan) -> bool {
+        self.value > other.value || (self.value == other.value && self.sender_id < other.sender_id)
+    }
+}
+
+#[derive(Default)]
+struct PeerGame {
+    agent_ids: Vec<String>,
+    // Keyed by turn and sender, so every peer's plan is kept
+    plans: HashMap<(i32, String), PeerPlan>,
+}
+
+pub struct Peers {
+    config: Option<PeerConfig>,
+    inbox: Mutex<HashMap<String, PeerGame>>,
+    arrived: Condvar,
+}
+
+pub type SharedPeers = Arc<Peers>;
+
+impl Peers {
+    pub fn new(config: Option<PeerConfig>) -> Self {
+        Peers {
+            config,
+            inbox: Mutex::new(HashMap::new()),
+            arrived: Condvar::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    pub fn margin(&self) -> u32 {
+        self.config.as_ref().map_or(0, |c| c.margin)
+    }
+
+    pub fn authorized(&self, token: &str) -> bool {
+        self.config
+            .as_ref()
+            .is_some_and(|c| !c.token.is_empty() && constant_time_eq(c.token.as_bytes(), token.as_bytes()))
+    }
+
+    pub fn receive_hello(&self, hello: PeerHello) {
+        let mut inbox = self.inbox.lock().unwrap();
+        let game = inbox.entry(hello.game_id).or_default();
+        if !game.agent_ids.contains(&hello.agent_id) {
+            game.agent_ids.push(hello.agent_id);
+        }
+    }
+
+    pub fn receive_plan(&self, plan: PeerPlan) {
+        let mut inbox = self.inbox.lock().unwrap();
+        let game = inbox.entry(plan.game_id.clone()).or_default();
+        if !game.agent_ids.contains(&plan.sender_id) {
+            game.agent_ids.push(plan.sender_id.clone());
+        }
+        game.plans.insert((plan.turn, plan.sender_id.clone()), plan);
+        self.arrived.notify_all();
+    }
+
+    // Snake ids announced by peers for a game
+    pub fn peer_ids(&self, game_id: &str) -> Vec<String> {
+        let inbox = self.inbox.lock().unwrap();
+        inbox
+            .get(game_id)
+            .map_or_else(Vec::new, |game| game.agent_ids.clone())
+    }
+
+    // Blocks until every peer sent its plan for the turn or the deadline passes, and
+    // returns the plans that arrived
+    pub fn wait_for_plans(&self, game_id: &str, turn: i32, deadline: Instant) -> Vec<PeerPlan> {
+        let expected = self.config.as_ref().map_or(1, |c| c.urls.len());
+        let mut inbox = self.inbox.lock().unwrap();
+        loop {
+            let plans: Vec<PeerPlan> = inbox.get(game_id).map_or_else(Vec::new, |g| {
+                g.plans.values().filter(|p| p.turn == turn).cloned().collect()
+            });
+            let now = Instant::now();
+            if plans.len() >= expected || now >= deadline {
+                return plans;
+            }
+            inbox = self.arrived.wait_timeout(inbox, deadline - now).unwrap().0;
+        }
+    }
+
+    pub fn end_game(&self, game_id: &str) {
+        self.inbox.lock().unwrap().remove(game_id);
+    }
+
+    pub fn send_hello(&self, hello: &PeerHello) {
+        self.broadcast("/peer/hello", serde_json::to_string(hello).unwrap());
+    }
+
+    pub fn send_plan(&self, plan: &PeerPlan) {
+        self.broadcast("/peer/plan", serde_json::to_string(plan).unwrap());
+    }
+
+    // Sends in the background so a slow peer never holds up our own response
+    fn broadcast(&self, path: &'static str, body: String) {
+        let Some(config) = &self.config else {
+            return;
+        };
+        let timeout = Duration::from_millis(config.margin.max(1) as u64);
+        for url in &config.urls {
+            let url = url.clone();
+            let token = config.token.clone();
+            let body = body.clone();
+            thread::spawn(move || {
+                if let Err(e) = post_json(&url, path, &token, &body, timeout) {
+                    info!("Peer {}{} unreachable: {}", url, path, e);
+                }
+            });
+        }
+    }
+}
+
+// Minimal HTTP/1.1 client, peers are expected to be reachable over plain http
+fn post_json(
+    base_url: &str,
+    path: &str,
+    token: &str,
+    body: &str,
+    timeout: Duration,
+) -> std::io::Result<()> {
+    let host = base_url.trim_start_matches("http://");
+    let addr = host.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::NotFound, format!("no address for {}", host))
+    })?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        token,
+        body.len(),
+        body
+    )?;
+    let mut status_line = [0u8; 12];
+    stream.read_exact(&mut status_line)?;
+    let status = String::from_utf8_lossy(&status_line[9..12]).to_string();
+    if status != "200" {
+        return Err(std::io::Error::other(format!("peer answered {}", status)));
+    }
+    Ok(())
+}
+
+// Takes as long for every token of the right length however many bytes match
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Request guard for the peer routes, checks the bearer token against team.peer_token
+pub struct PeerAuth;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PeerAuth {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let peers = request.rocket().state::<SharedPeers>();
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "));
+        match (peers, token) {
+            (Some(peers), Some(token)) if peers.authorized(token) => Outcome::Success(PeerAuth),
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{post_json, PeerConfig, PeerPlan, Peers};
+    use crate::logic::simple::Movement;
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn plan(sender_id: &str, turn: i32, value: i32) -> PeerPlan {
+        PeerPlan {
+            game_id: "game".to_string(),
+            turn,
+            sender_id: sender_id.to_string(),
+            moves: HashMap::from([(sender_id.to_string(), Movement::Up)]),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_wait_for_plan() {
+        let peers = Arc::new(Peers::new(None));
+        let sender = peers.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            sender.receive_plan(plan("b", 3, 5));
+        });
+
+        let deadline = Instant::now() + Duration::from_millis(500);
+        let received = peers.wait_for_plans("game", 3, deadline);
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].sender_id, "b");
+        assert_eq!(peers.peer_ids("game"), vec!["b".to_string()]);
+
+        // Nothing arrives for the next turn
+        let deadline = Instant::now() + Duration::from_millis(20);
+        assert!(peers.wait_for_plans("game", 4, deadline).is_empty());
+    }
+
+    #[test]
+    fn test_plans_of_every_peer_are_kept() {
+        let peers = Peers::new(Some(PeerConfig {
+            urls: vec!["http://localhost:8001".to_string(), "http://localhost:8002".to_string()],
+            token: "secret".to_string(),
+            margin: 60,
+        }));
+        peers.receive_plan(plan("c", 3, 5));
+        peers.receive_plan(plan("b", 3, 7));
+        peers.receive_plan(plan("b", 4, 1));
+        let deadline = Instant::now() + Duration::from_millis(500);
+        let mut received: Vec<_> = peers.wait_for_plans("game", 3, deadline).into_iter().map(|p| p.sender_id).collect();
+        received.sort();
+        assert_eq!(received, vec!["b".to_string(), "c".to_string()]);
+    }
+
+    #[test]
+    fn test_plan_order_is_symmetric() {
+        let a = plan("a", 0, 10);
+        let b = plan("b", 0, 10);
+        assert!(a.better_than(&b));
+        assert!(!b.better_than(&a));
+        assert!(plan("b", 0, 11).better_than(&a));
+    }
+
+    #[test]
+    fn test_authorization() {
+        let peers = Peers::new(Some(PeerConfig {
+            urls: vec!["http://localhost:8001".to_string()],
+            token: "secret".to_string(),
+            margin: 60,
+        }));
+        assert!(peers.authorized("secret"));
+        assert!(!peers.authorized("guess"));
+        assert!(!peers.authorized("secret2"));
+        assert!(!peers.authorized(""));
+        assert!(!Peers::new(None).authorized(""));
+        // Peers without a token accept nothing
+        let open = Peers::new(Some(PeerConfig {
+            urls: vec!["http://localhost:8001".to_string()],
+            token: String::new(),
+            margin: 60,
+        }));
+        assert!(!open.authorized(""));
+    }
+
+    #[test]
+    fn test_post_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![0u8; 1024];
+            let n = stream.read(&mut request).unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            String::from_utf8_lossy(&request[..n]).to_string()
+        });
+
+        post_json(&url, "/peer/plan", "secret", "{}", Duration::from_millis(500)).unwrap();
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /peer/plan HTTP/1.1"));
+        assert!(request.contains("Authorization: Bearer secret"));
+        assert!(request.ends_with("{}"));
+    }
+}
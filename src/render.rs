@@ -0,0 +1,327 @@
+// Pictures of boards for the terminal and for bug reports.
+//
+// Every snake gets a letter and a color by its slot, the head in upper case and the body
+// in lower case, so snakes can be told apart without colors too. `terminal` draws the
+// board with coordinate axes, optionally with ANSI colors, and `svg` and `svg_replay`
+// write standalone SVG files of one position or of every turn of a game.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::logic::simple::SimpleBoard;
+use crate::{Board, Coord};
+
+const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+// ANSI foreground color and SVG fill for each snake slot, cycled for larger games
+const PALETTE: [(&str, &str); 8] = [
+    ("\x1b[91m", "#e6194b"),
+    ("\x1b[94m", "#4363d8"),
+    ("\x1b[92m", "#3cb44b"),
+    ("\x1b[93m", "#d4a017"),
+    ("\x1b[95m", "#911eb4"),
+    ("\x1b[96m", "#42d4f4"),
+    ("\x1b[33m", "#f58231"),
+    ("\x1b[35m", "#f032e6"),
+];
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_HAZARD: &str = "\x1b[48;5;236m";
+const ANSI_FOOD: &str = "\x1b[32m";
+
+const CELL: i32 = 24;
+// Room for the axis labels around an SVG board
+const MARGIN: i32 = 20;
+const REPLAY_COLUMNS: usize = 5;
+
+// What is drawn of a board. Snakes keep their slot when they die so letters and colors
+// stay the same from turn to turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scene {
+    pub width: i32,
+    pub height: i32,
+    pub food: Vec<Coord>,
+    pub hazards: Vec<Coord>,
+    pub snakes: Vec<Option<Vec<Coord>>>,
+    pub title: Option<String>,
+}
+
+impl From<&Board> for Scene {
+    fn from(board: &Board) -> Self {
+        Scene {
+            width: board.width,
+            height: board.height as i32,
+            food: board.food.clone(),
+            hazards: board.hazards.clone(),
+            snakes: board.snakes.iter().map(|s| Some(s.body.clone())).collect(),
+            title: None,
+        }
+    }
+}
+
+impl From<&SimpleBoard> for Scene {
+    fn from(board: &SimpleBoard) -> Self {
+        Scene {
+            width: 11,
+            height: 11,
+            food: board.food.clone(),
+            hazards: vec![],
+            snakes: board
+                .snakes
+                .iter()
+                .map(|s| s.as_ref().map(|s| s.body().iter().copied().collect()))
+                .collect(),
+            title: None,
+        }
+    }
+}
+
+impl Scene {
+    pub fn with_title(mut self, title: String) -> Self {
+        self.title = Some(title);
+        self
+    }
+
+    // One scene per board of a game, with the slots from `slots` so eliminated snakes
+    // don't shift the others' letters
+    pub fn replay(boards: &[Board]) -> Vec<Scene> {
+        let slots = slots(boards);
+        boards
+            .iter()
+            .map(|board| {
+                let mut scene = Scene::from(board);
+                scene.snakes = vec![None; slots.len()];
+                for snake in &board.snakes {
+                    scene.snakes[slots[snake.id.as_str()]] = Some(snake.body.clone());
+                }
+                scene
+            })
+            .collect()
+    }
+
+    fn snake_at(&self, c: Coord) -> Option<(usize, bool)> {
+        self.snakes.iter().enumerate().find_map(|(i, body)| {
+            let body = body.as_ref()?;
+            let pos = body.iter().position(|&b| b == c)?;
+            Some((i, pos == 0))
+        })
+    }
+}
+
+// Slot of every snake id in a game, in the order the ids first appear
+pub fn slots(boards: &[Board]) -> HashMap<&str, usize> {
+    let mut slots: HashMap<&str, usize> = HashMap::new();
+    for snake in boards.iter().flat_map(|b| b.snakes.iter()) {
+        let next = slots.len();
+        slots.entry(snake.id.as_str()).or_insert(next);
+    }
+    slots
+}
+
+pub fn letter(slot: usize, head: bool) -> char {
+    let c = LETTERS[slot % LETTERS.len()] as char;
+    if head {
+        c.to_ascii_uppercase()
+    } else {
+        c
+    }
+}
+
+// The board as text, top row first, with the y axis on the left and the x axis below.
+// Food is `*`, hazards without a snake or food on them are `~` and empty cells `.`.
+pub fn terminal(scene: &Scene, color: bool) -> String {
+    let mut out = String::new();
+    if let Some(title) = &scene.title {
+        writeln!(out, "{}", title).unwrap();
+    }
+    let border = format!("   +{}+", "-".repeat(scene.width as usize * 2 + 1));
+    writeln!(out, "{}", border).unwrap();
+    for y in (0..scene.height).rev() {
+        write!(out, "{:>2} | ", y).unwrap();
+        for x in 0..scene.width {
+            let c = Coord { x, y };
+            let hazard = scene.hazards.contains(&c);
+            let (glyph, fg) = match scene.snake_at(c) {
+                Some((slot, head)) => (letter(slot, head), PALETTE[slot % PALETTE.len()].0),
+                None if scene.food.contains(&c) => ('*', ANSI_FOOD),
+                None if hazard => ('~', ""),
+                None => ('.', ""),
+            };
+            if color && (hazard || !fg.is_empty()) {
+                let bg = if hazard { ANSI_HAZARD } else { "" };
+                write!(out, "{}{}{}{} ", bg, fg, glyph, ANSI_RESET).unwrap();
+            } else {
+                write!(out, "{} ", glyph).unwrap();
+            }
+        }
+        writeln!(out, "|").unwrap();
+    }
+    writeln!(out, "{}", border).unwrap();
+    write!(out, "     ").unwrap();
+    for x in 0..scene.width {
+        write!(out, "{:<2}", x).unwrap();
+    }
+    out.trim_end().to_string()
+}
+
+// A standalone SVG picture of one position
+pub fn svg(scene: &Scene) -> String {
+    let (w, h) = frame_size(scene);
+    let mut out = svg_header(w, h);
+    draw_frame(&mut out, scene, 0, 0);
+    out.push_str("</svg>\n");
+    out
+}
+
+// Every scene of a replay side by side in rows, each labelled with its title
+pub fn svg_replay(scenes: &[Scene]) -> String {
+    let Some(first) = scenes.first() else {
+        return svg_header(0, 0) + "</svg>\n";
+    };
+    let (w, h) = frame_size(first);
+    let columns = scenes.len().min(REPLAY_COLUMNS);
+    let rows = scenes.len().div_ceil(REPLAY_COLUMNS);
+    let mut out = svg_header(w * columns as i32, h * rows as i32);
+    for (i, scene) in scenes.iter().enumerate() {
+        let x = (i % REPLAY_COLUMNS) as i32 * w;
+        let y = (i / REPLAY_COLUMNS) as i32 * h;
+        draw_frame(&mut out, scene, x, y);
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+fn frame_size(scene: &Scene) -> (i32, i32) {
+    (scene.width * CELL + 2 * MARGIN, scene.height * CELL + 2 * MARGIN)
+}
+
+fn svg_header(w: i32, h: i32) -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" \
+         font-family=\"monospace\" font-size=\"12\" text-anchor=\"middle\" dominant-baseline=\"central\">\n"
+    )
+}
+
+fn draw_frame(out: &mut String, scene: &Scene, x0: i32, y0: i32) {
+    writeln!(out, "<g transform=\"translate({} {})\">", x0, y0).unwrap();
+    if let Some(title) = &scene.title {
+        writeln!(out, "<text x=\"{}\" y=\"{}\">{}</text>", frame_size(scene).0 / 2, MARGIN / 2, escape(title)).unwrap();
+    }
+    // Board coordinates have y pointing up, SVG has it pointing down
+    let cell = |c: Coord| (MARGIN + c.x * CELL, MARGIN + (scene.height - 1 - c.y) * CELL);
+    for y in 0..scene.height {
+        for x in 0..scene.width {
+            let (px, py) = cell(Coord { x, y });
+            let fill = if scene.hazards.contains(&Coord { x, y }) { "#bbbbbb" } else { "#f4f4f4" };
+            writeln!(out, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"#dddddd\"/>", px, py, CELL, CELL, fill).unwrap();
+        }
+    }
+    for y in 0..scene.height {
+        let (_, py) = cell(Coord { x: 0, y });
+        writeln!(out, "<text x=\"{}\" y=\"{}\" fill=\"#888888\">{}</text>", MARGIN / 2, py + CELL / 2, y).unwrap();
+    }
+    for x in 0..scene.width {
+        let (px, _) = cell(Coord { x, y: 0 });
+        let py = MARGIN + scene.height * CELL + MARGIN / 2;
+        writeln!(out, "<text x=\"{}\" y=\"{}\" fill=\"#888888\">{}</text>", px + CELL / 2, py, x).unwrap();
+    }
+    for &f in &scene.food {
+        let (px, py) = cell(f);
+        writeln!(out, "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"#2e8b57\"/>", px + CELL / 2, py + CELL / 2, CELL / 5).unwrap();
+    }
+    for (slot, body) in scene.snakes.iter().enumerate() {
+        let Some(body) = body else { continue };
+        let fill = PALETTE[slot % PALETTE.len()].1;
+        // Drawn tail first so the head ends up on top of stacked segments
+        for (i, &c) in body.iter().enumerate().rev() {
+            let (px, py) = cell(c);
+            let inset = if i == 0 { 1 } else { 3 };
+            writeln!(
+                out,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"4\" fill=\"{}\"/>",
+                px + inset,
+                py + inset,
+                CELL - 2 * inset,
+                CELL - 2 * inset,
+                fill
+            )
+            .unwrap();
+        }
+        let (px, py) = cell(body[0]);
+        writeln!(out, "<text x=\"{}\" y=\"{}\" fill=\"#ffffff\" font-weight=\"bold\">{}</text>", px + CELL / 2, py + CELL / 2, letter(slot, true)).unwrap();
+    }
+    out.push_str("</g>\n");
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{svg, svg_replay, terminal, Scene};
+    use crate::Coord;
+
+    fn scene() -> Scene {
+        Scene {
+            width: 3,
+            height: 2,
+            food: vec![Coord { x: 2, y: 1 }],
+            hazards: vec![Coord { x: 2, y: 0 }],
+            snakes: vec![
+                Some(vec![Coord { x: 0, y: 1 }, Coord { x: 0, y: 0 }]),
+                None,
+                Some(vec![Coord { x: 1, y: 0 }]),
+            ],
+            title: None,
+        }
+    }
+
+    #[test]
+    fn test_terminal_plain() {
+        let expected = [
+            "   +-------+",
+            " 1 | A . * |",
+            " 0 | a C ~ |",
+            "   +-------+",
+            "     0 1 2",
+        ]
+        .join("\n");
+        assert_eq!(terminal(&scene(), false), expected);
+    }
+
+    #[test]
+    fn test_terminal_color() {
+        let text = terminal(&scene(), true);
+        assert!(text.contains("\x1b[91mA\x1b[0m"));
+        assert!(text.contains("\x1b[48;5;236m~\x1b[0m"));
+        // Colors don't change what is drawn
+        assert_eq!(strip_ansi(&text), terminal(&scene(), false));
+    }
+
+    fn strip_ansi(text: &str) -> String {
+        let mut out = String::new();
+        let mut escape = false;
+        for c in text.chars() {
+            if c == '\x1b' {
+                escape = true;
+            } else if escape {
+                escape = c != 'm';
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_svg_replay_lays_out_frames() {
+        let scenes: Vec<Scene> = (0..7).map(|t| scene().with_title(format!("turn {}", t))).collect();
+        let single = svg(&scenes[0]);
+        assert!(single.starts_with("<svg") && single.ends_with("</svg>\n"));
+        let replay = svg_replay(&scenes);
+        assert_eq!(replay.matches("<g transform").count(), 7);
+        assert!(replay.contains("turn 6"));
+        // Five frames in the first row, two in the second
+        assert!(replay.contains("width=\"560\" height=\"176\""));
+    }
+}
@@ -0,0 +1,266 @@
+// Recorded games and the viewer served at /replays.
+//
+// Every answered /move appends one line to `<replays.dir>/<game id>.jsonl` with the
+// board, the move our snake played and the value and depth of the search behind it, and
+// /end adds the final board. Snakes of ours that share a game write to the same file, so
+// a turn can have several lines. The viewer is a single HTML page with the boards
+// pre-rendered as SVG and a few lines of script to step through them.
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::logic::simple::Movement;
+use crate::render::{self, Scene};
+use crate::Board;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub turn: i32,
+    // Our snake that answered the request
+    pub snake: String,
+    pub board: Board,
+    // None for the final board from /end
+    #[serde(rename = "move")]
+    pub chosen: Option<Movement>,
+    // Search value and depth, None when the move came from the book or a team mate
+    pub value: Option<i32>,
+    pub depth: Option<i32>,
+}
+
+// One turn of a recorded game with what each of our snakes played
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub turn: i32,
+    pub board: Board,
+    pub moves: Vec<Record>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    pub id: String,
+    pub turns: usize,
+    // seconds since the epoch the file was last written
+    pub modified: u64,
+}
+
+// Game ids end up in file names, anything but a plain id is refused
+fn valid_id(game_id: &str) -> bool {
+    !game_id.is_empty() && game_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn path(dir: &str, game_id: &str) -> Option<PathBuf> {
+    if dir.is_empty() || !valid_id(game_id) {
+        return None;
+    }
+    Some(Path::new(dir).join(format!("{}.jsonl", game_id)))
+}
+
+// Appends a record to the game's file, recording is off when dir is empty
+pub fn record(dir: &str, game_id: &str, record: &Record) {
+    let Some(path) = path(dir, game_id) else {
+        return;
+    };
+    let written = fs::create_dir_all(dir).and_then(|_| {
+        // One write per line, records of team mates are appended from several threads
+        let line = format!("{}\n", serde_json::to_string(record)?);
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        file.write_all(line.as_bytes())
+    });
+    if let Err(e) = written {
+        warn!("Could not record turn {} to {}: {}", record.turn, path.display(), e);
+    }
+}
+
+// Recorded games, the most recent first
+pub fn list(dir: &str) -> Vec<Summary> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut games: Vec<Summary> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension()? != "jsonl" {
+                return None;
+            }
+            let id = path.file_stem()?.to_string_lossy().to_string();
+            let modified = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            let turns = load(dir, &id).map_or(0, |frames| frames.len());
+            Some(Summary { id, turns, modified })
+        })
+        .collect();
+    games.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.id.cmp(&b.id)));
+    games
+}
+
+// The turns of a recorded game in order, lines that don't parse are skipped
+pub fn load(dir: &str, game_id: &str) -> Option<Vec<Frame>> {
+    let contents = fs::read_to_string(path(dir, game_id)?).ok()?;
+    let mut frames: BTreeMap<i32, Frame> = BTreeMap::new();
+    for record in contents.lines().filter_map(|line| serde_json::from_str::<Record>(line).ok()) {
+        let frame = frames.entry(record.turn).or_insert_with(|| Frame {
+            turn: record.turn,
+            board: record.board.clone(),
+            moves: vec![],
+        });
+        if record.chosen.is_some() && !frame.moves.iter().any(|m| m.snake == record.snake) {
+            frame.moves.push(record);
+        }
+    }
+    Some(frames.into_values().collect())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}table{border-collapse:collapse}\
+td,th{padding:.2em .8em;text-align:left}tr:nth-child(even){background:#f4f4f4}";
+
+pub fn index_html(games: &[Summary]) -> String {
+    let rows: String = games
+        .iter()
+        .map(|g| {
+            format!(
+                "<tr><td><a href=\"/replays/{id}\">{id}</a></td><td>{}</td><td class=\"time\" data-t=\"{}\"></td></tr>",
+                g.turns,
+                g.modified,
+                id = escape(&g.id)
+            )
+        })
+        .collect();
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Replays</title><style>{STYLE}</style></head><body>\
+         <h1>Replays</h1><table><tr><th>Game</th><th>Turns</th><th>Recorded</th></tr>{rows}</table>\
+         <script>for(const td of document.querySelectorAll('.time'))\
+         td.textContent=new Date(td.dataset.t*1000).toLocaleString()</script></body></html>"
+    )
+}
+
+pub fn viewer_html(game_id: &str, frames: &[Frame]) -> String {
+    let boards: Vec<Board> = frames.iter().map(|f| f.board.clone()).collect();
+    let slots = render::slots(&boards);
+    let scenes = Scene::replay(&boards);
+    let data: Vec<_> = frames
+        .iter()
+        .zip(&scenes)
+        .map(|(frame, scene)| {
+            let moves: Vec<_> = frame
+                .moves
+                .iter()
+                .map(|m| {
+                    let letter = slots.get(m.snake.as_str()).map(|&s| render::letter(s, true).to_string());
+                    json!({ "snake": m.snake, "letter": letter, "move": m.chosen, "value": m.value, "depth": m.depth })
+                })
+                .collect();
+            json!({ "turn": frame.turn, "svg": render::svg(scene), "moves": moves })
+        })
+        .collect();
+    // Script end tags inside the JSON would close the script element
+    let data = serde_json::to_string(&data).unwrap().replace("</", "<\\/");
+    let id = escape(game_id);
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Replay {id}</title><style>{STYLE}\
+         #board{{margin:1em 0}}button{{min-width:3em}}</style></head><body>\
+         <p><a href=\"/replays\">All replays</a></p><h1>{id}</h1>\
+         <div><button id=\"first\">|&lt;</button><button id=\"prev\">&lt;</button>\
+         <input id=\"slider\" type=\"range\" min=\"0\" value=\"0\"><button id=\"next\">&gt;</button>\
+         <button id=\"last\">&gt;|</button> <button id=\"play\">play</button> <span id=\"turn\"></span></div>\
+         <div id=\"board\"></div><table id=\"moves\"></table>\
+         <script>\
+         const frames={data};let i=0,timer=null;const $=id=>document.getElementById(id);\
+         const esc=t=>String(t).replace(/[&<>]/g,c=>({{'&':'&amp;','<':'&lt;','>':'&gt;'}})[c]);\
+         $('slider').max=Math.max(frames.length-1,0);\
+         function show(n){{if(!frames.length)return;i=Math.max(0,Math.min(frames.length-1,n));const f=frames[i];\
+         $('slider').value=i;$('turn').textContent='turn '+f.turn;$('board').innerHTML=f.svg;\
+         $('moves').innerHTML='<tr><th>Snake</th><th>Move</th><th>Value</th><th>Depth</th></tr>'+\
+         f.moves.map(m=>'<tr><td>'+(m.letter||'')+' '+esc(m.snake)+'</td><td>'+m.move+'</td><td>'+\
+         (m.value??'')+'</td><td>'+(m.depth??'')+'</td></tr>').join('')}}\
+         $('first').onclick=()=>show(0);$('prev').onclick=()=>show(i-1);$('next').onclick=()=>show(i+1);\
+         $('last').onclick=()=>show(frames.length-1);$('slider').oninput=e=>show(+e.target.value);\
+         $('play').onclick=()=>{{if(timer){{clearInterval(timer);timer=null;return}}\
+         timer=setInterval(()=>{{if(i>=frames.length-1){{clearInterval(timer);timer=null}}else show(i+1)}},300)}};\
+         document.onkeydown=e=>{{if(e.key==='ArrowLeft')show(i-1);if(e.key==='ArrowRight')show(i+1)}};show(0);\
+         </script></body></html>"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{list, load, record, viewer_html, Record};
+    use crate::logic::simple::Movement;
+    use crate::{Battlesnake, Board, Coord};
+    use std::fs;
+
+    fn board(turn: i32) -> Board {
+        let snake = |id: &str, x: i32| Battlesnake {
+            id: id.to_string(),
+            name: id.to_string(),
+            health: 100 - turn,
+            body: vec![Coord { x, y: turn }],
+            head: Coord { x, y: turn },
+            length: 1,
+            latency: "0".to_string(),
+            shout: None,
+            squad: String::new(),
+        };
+        Board {
+            height: 11,
+            width: 11,
+            food: vec![],
+            snakes: vec![snake("a", 1), snake("b", 5)],
+            hazards: vec![],
+        }
+    }
+
+    fn turn(turn: i32, snake: &str, chosen: Option<Movement>) -> Record {
+        Record {
+            turn,
+            snake: snake.to_string(),
+            board: board(turn),
+            chosen,
+            value: chosen.map(|_| 10 * turn),
+            depth: chosen.map(|_| 4),
+        }
+    }
+
+    #[test]
+    fn test_record_and_load() {
+        let dir = std::env::temp_dir().join(format!("replays-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        record(dir, "game-1", &turn(0, "a", Some(Movement::Up)));
+        record(dir, "game-1", &turn(0, "b", Some(Movement::Left)));
+        record(dir, "game-1", &turn(1, "a", Some(Movement::Up)));
+        record(dir, "game-1", &turn(2, "a", None));
+        // Ids that could leave the directory are not recorded
+        record(dir, "../game", &turn(0, "a", Some(Movement::Up)));
+
+        let frames = load(dir, "game-1").unwrap();
+        assert_eq!(frames.iter().map(|f| f.turn).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(frames[0].moves.len(), 2);
+        assert_eq!(frames[0].moves[1].chosen, Some(Movement::Left));
+        assert!(frames[2].moves.is_empty());
+        assert!(load(dir, "../game").is_none());
+
+        let games = list(dir);
+        assert_eq!(games.len(), 1);
+        assert_eq!((games[0].id.as_str(), games[0].turns), ("game-1", 3));
+
+        let html = viewer_html("game-1", &frames);
+        assert!(html.contains("\"move\":\"left\""));
+        assert!(html.contains("\"letter\":\"B\""));
+        assert!(!html.contains("</svg>"), "SVG end tags must be escaped inside the script");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
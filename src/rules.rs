@@ -0,0 +1,281 @@
+// Reference implementation of the standard Battlesnake rules on the API types.
+//
+// One turn runs the same pipeline as the official engine: move every snake, reduce health,
+// apply hazard damage, feed the snakes on food and finally eliminate the snakes that
+// starved, left the board or collided. Food spawning is random in the official engine and
+// left out, so a step is fully determined by the board and the moves.
+//
+// This is written for clarity rather than speed. The search uses SimpleBoard, which is
+// checked against this module with the fixtures in fixtures/rules.
+
+use std::collections::HashMap;
+
+use crate::logic::simple::Movement;
+use crate::{Battlesnake, Board, Coord};
+
+// Health lost per turn with the head in a hazard, the standard ruleset default
+pub const HAZARD_DAMAGE: i32 = 14;
+pub const MAX_HEALTH: i32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Elimination {
+    OutOfHealth,
+    OutOfBounds,
+    SelfCollision,
+    Collision,
+    HeadToHead,
+}
+
+// The board one turn later, without the eliminated snakes
+pub fn step(board: &Board, moves: &HashMap<String, Movement>, hazard_damage: i32) -> Board {
+    step_with_eliminations(board, moves, hazard_damage).0
+}
+
+pub fn step_with_eliminations(
+    board: &Board,
+    moves: &HashMap<String, Movement>,
+    hazard_damage: i32,
+) -> (Board, Vec<(String, Elimination)>) {
+    let mut next = board.clone();
+    move_snakes(&mut next, moves);
+    reduce_health(&mut next);
+    damage_hazards(&mut next, hazard_damage);
+    feed_snakes(&mut next);
+    let eliminated = eliminate_snakes(&next);
+    next.snakes.retain(|s| !eliminated.iter().any(|(id, _)| id == &s.id));
+    (next, eliminated)
+}
+
+// Snakes without a move keep going the way they were facing, or up if that's unknown
+pub fn default_move(snake: &Battlesnake) -> Movement {
+    if snake.body.len() < 2 || snake.body[0] == snake.body[1] {
+        return Movement::Up;
+    }
+    let (head, neck) = (snake.body[0], snake.body[1]);
+    if head.x > neck.x {
+        Movement::Right
+    } else if head.x < neck.x {
+        Movement::Left
+    } else if head.y < neck.y {
+        Movement::Down
+    } else {
+        Movement::Up
+    }
+}
+
+fn next_head(head: Coord, mv: Movement) -> Coord {
+    match mv {
+        Movement::Up => Coord { x: head.x, y: head.y + 1 },
+        Movement::Down => Coord { x: head.x, y: head.y - 1 },
+        Movement::Left => Coord { x: head.x - 1, y: head.y },
+        Movement::Right => Coord { x: head.x + 1, y: head.y },
+        Movement::None => head,
+    }
+}
+
+fn move_snakes(board: &mut Board, moves: &HashMap<String, Movement>) {
+    for snake in board.snakes.iter_mut() {
+        let mv = moves.get(&snake.id).copied().unwrap_or_else(|| default_move(snake));
+        let head = next_head(snake.body[0], mv);
+        snake.body.insert(0, head);
+        snake.body.pop();
+        snake.head = head;
+    }
+}
+
+fn reduce_health(board: &mut Board) {
+    for snake in board.snakes.iter_mut() {
+        snake.health -= 1;
+    }
+}
+
+fn damage_hazards(board: &mut Board, hazard_damage: i32) {
+    for snake in board.snakes.iter_mut() {
+        let head = snake.body[0];
+        // Food cancels the damage, the snake is healed in the next step anyway
+        if board.food.contains(&head) {
+            continue;
+        }
+        let hazards = board.hazards.iter().filter(|&&h| h == head).count() as i32;
+        snake.health = (snake.health - hazards * hazard_damage).max(0);
+    }
+}
+
+fn feed_snakes(board: &mut Board) {
+    let mut eaten = Vec::new();
+    for snake in board.snakes.iter_mut() {
+        let head = snake.body[0];
+        if board.food.contains(&head) {
+            snake.health = MAX_HEALTH;
+            let tail = *snake.body.last().unwrap();
+            snake.body.push(tail);
+            eaten.push(head);
+        }
+        snake.length = snake.body.len() as i32;
+    }
+    board.food.retain(|f| !eaten.contains(f));
+}
+
+fn out_of_bounds(board: &Board, c: Coord) -> bool {
+    c.x < 0 || c.y < 0 || c.x >= board.width || c.y >= board.height as i32
+}
+
+fn eliminate_snakes(board: &Board) -> Vec<(String, Elimination)> {
+    let mut eliminated = Vec::new();
+    for snake in &board.snakes {
+        if snake.health <= 0 {
+            eliminated.push((snake.id.clone(), Elimination::OutOfHealth));
+        } else if out_of_bounds(board, snake.body[0]) {
+            eliminated.push((snake.id.clone(), Elimination::OutOfBounds));
+        }
+    }
+
+    // Collisions only count against the snakes that are still on the board
+    let alive: Vec<&Battlesnake> = board
+        .snakes
+        .iter()
+        .filter(|s| !eliminated.iter().any(|(id, _)| id == &s.id))
+        .collect();
+    let mut collisions = Vec::new();
+    for snake in &alive {
+        let head = snake.body[0];
+        if snake.body[1..].contains(&head) {
+            collisions.push((snake.id.clone(), Elimination::SelfCollision));
+        } else if alive
+            .iter()
+            .any(|other| other.id != snake.id && other.body[1..].contains(&head))
+        {
+            collisions.push((snake.id.clone(), Elimination::Collision));
+        } else if alive.iter().any(|other| {
+            other.id != snake.id && other.body[0] == head && other.body.len() >= snake.body.len()
+        }) {
+            collisions.push((snake.id.clone(), Elimination::HeadToHead));
+        }
+    }
+    eliminated.extend(collisions);
+    eliminated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_move, step, HAZARD_DAMAGE};
+    use crate::logic::simple::{JointMove, Movement, SimpleBoard, SimpleSnake};
+    use crate::{Board, Coord};
+    use serde::Deserialize;
+    use std::collections::HashMap;
+    use std::fs;
+
+    #[derive(Deserialize)]
+    struct Fixture {
+        description: String,
+        board: Board,
+        moves: HashMap<String, Movement>,
+        #[serde(default)]
+        hazard_damage: Option<i32>,
+        expected: Board,
+    }
+
+    fn fixtures() -> Vec<(String, Fixture)> {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/rules");
+        let mut files: Vec<_> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+        files.sort();
+        files
+            .into_iter()
+            .filter(|p| p.extension().is_some_and(|e| e == "json"))
+            .map(|p| {
+                let name = p.file_name().unwrap().to_string_lossy().to_string();
+                let fixture = serde_json::from_str(&fs::read_to_string(&p).unwrap())
+                    .unwrap_or_else(|e| panic!("{}: {}", name, e));
+                (name, fixture)
+            })
+            .collect()
+    }
+
+    fn snakes(board: &Board) -> Vec<(String, i32, Vec<Coord>)> {
+        let mut snakes: Vec<_> = board
+            .snakes
+            .iter()
+            .map(|s| (s.id.clone(), s.health, s.body.clone()))
+            .collect();
+        snakes.sort_by(|a, b| a.0.cmp(&b.0));
+        snakes
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let fixtures = fixtures();
+        assert!(fixtures.len() >= 10);
+        for (name, f) in fixtures {
+            let next = step(&f.board, &f.moves, f.hazard_damage.unwrap_or(HAZARD_DAMAGE));
+            assert_eq!(snakes(&next), snakes(&f.expected), "{}: {}", name, f.description);
+            let mut food = next.food.clone();
+            let mut expected_food = f.expected.food.clone();
+            food.sort_by_key(|c| (c.x, c.y));
+            expected_food.sort_by_key(|c| (c.x, c.y));
+            assert_eq!(food, expected_food, "{}: {}", name, f.description);
+            for snake in &next.snakes {
+                assert_eq!(snake.head, snake.body[0], "{}", name);
+                assert_eq!(snake.length as usize, snake.body.len(), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_move() {
+        let f = &fixtures()[0].1;
+        let snake = &f.board.snakes[0];
+        assert_eq!(snake.body[0].y, snake.body[1].y + 1);
+        assert_eq!(default_move(snake), Movement::Up);
+    }
+
+    // SimpleBoard with the first snake as our team and every other snake as opponents
+    fn simple_board(board: &Board) -> SimpleBoard {
+        let snakes = board
+            .snakes
+            .iter()
+            .map(|s| Some(SimpleSnake::new(s.body.clone(), s.health)))
+            .collect();
+        let teams = vec![vec![0], (1..board.snakes.len()).collect()];
+        SimpleBoard::new(board.food.clone(), snakes, teams)
+    }
+
+    fn pick(children: Vec<(JointMove, SimpleBoard)>, board: &Board, moves: &HashMap<String, Movement>) -> Option<SimpleBoard> {
+        children
+            .into_iter()
+            .find(|(m, _)| m.iter().all(|s| moves.get(&board.snakes[s.id].id) == Some(&s.mv)))
+            .map(|(_, b)| b)
+    }
+
+    // The search only plays safe moves, so fixtures with a move the simulator doesn't
+    // generate, hazards or more than one snake reaching the same food are skipped
+    #[test]
+    fn test_simple_board_matches_rules() {
+        let mut compared = 0;
+        for (name, f) in fixtures() {
+            if !f.board.hazards.is_empty() || f.board.snakes.iter().any(|s| !f.moves.contains_key(&s.id)) {
+                continue;
+            }
+            let simple = simple_board(&f.board);
+            let Some(ours) = pick(simple.simulate_move(true), &f.board, &f.moves) else {
+                continue;
+            };
+            let Some(next) = pick(ours.simulate_move(false), &f.board, &f.moves) else {
+                continue;
+            };
+            let expected = step(&f.board, &f.moves, HAZARD_DAMAGE);
+            let mut actual: Vec<(String, i32, Vec<Coord>)> = next
+                .snakes
+                .iter()
+                .enumerate()
+                .filter_map(|(i, s)| {
+                    s.as_ref()
+                        .map(|s| (f.board.snakes[i].id.clone(), s.health(), s.body().iter().copied().collect()))
+                })
+                .collect();
+            actual.sort_by(|a, b| a.0.cmp(&b.0));
+            assert_eq!(actual, snakes(&expected), "{}: {}", name, f.description);
+            compared += 1;
+        }
+        assert!(compared >= 5, "only {} fixtures compared", compared);
+    }
+}